@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use clap::ValueEnum;
+use lib::{invert_sequence, moves, Connected, GanRobotController};
+use serde::Serialize;
+use tokio::time::Instant;
+
+/// The output format of [`crate::Command::Benchmark`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    moves: usize,
+    total_ms: u128,
+    estimated_ms: u128,
+    moves_per_second: f64,
+    average_move_ms: f64,
+    /// How much longer the sequence took than estimated, negative if it was faster.
+    discrepancy_percent: f64,
+}
+
+/// Does a fixed sequence of every face and turn `rounds` times, followed by its inverse so the
+/// cube ends up as it was, and prints how fast the robot was compared to the estimate.
+pub async fn benchmark(
+    controller: &GanRobotController<Connected>,
+    rounds: usize,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let round = moves!("R F D L B R2 F2 D2 L2 B2 R' F' D' L' B'");
+    let round = [round.clone(), invert_sequence(&round)].concat();
+    let moves = round.repeat(rounds);
+
+    let start = Instant::now();
+    let timings = controller.do_moves_timed(&moves).await?;
+    let total = start.elapsed();
+    let estimated = controller.estimated_duration(&moves);
+
+    let moved = timings.iter().map(|(_, d)| *d).sum::<Duration>();
+    let report = Report {
+        moves: timings.len(),
+        total_ms: total.as_millis(),
+        estimated_ms: estimated.as_millis(),
+        moves_per_second: timings.len() as f64 / total.as_secs_f64(),
+        average_move_ms: moved.as_secs_f64() * 1000.0 / timings.len().max(1) as f64,
+        discrepancy_percent: (total.as_secs_f64() / estimated.as_secs_f64() - 1.0) * 100.0,
+    };
+    match output {
+        OutputFormat::Human => {
+            println!("Moves: {}", report.moves);
+            println!("Total: {} ms, estimated {} ms", report.total_ms, report.estimated_ms);
+            println!("Throughput: {:.2} moves/s", report.moves_per_second);
+            println!("Average move: {:.1} ms", report.average_move_ms);
+            println!("Discrepancy: {:+.1}%", report.discrepancy_percent);
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string(&report)?),
+    }
+    Ok(())
+}
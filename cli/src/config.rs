@@ -0,0 +1,261 @@
+use std::{collections::BTreeMap, fs, path::PathBuf, time::Duration};
+
+use lib::{
+    DroppedMoveCheck, Orientation, TimingConfig, WriteType, GAN_ROBOT_SERVICE, MAX_MOVES_PER_WRITE,
+};
+use serde::Deserialize;
+
+use crate::{parse_write_type, Args};
+
+const CONFIG_FILE_NAME: &str = "gan-robot.toml";
+
+pub const DEFAULT_NAME: &str = "GAN-a7f13";
+pub const DEFAULT_MOVE_CHARACTERISTIC: &str = "0000fff3-0000-1000-8000-00805f9b34fb";
+pub const DEFAULT_STATUS_CHARACTERISTIC: &str = "0000fff2-0000-1000-8000-00805f9b34fb";
+pub const DEFAULT_TIMEZONE: &str = "Asia/Tokyo";
+const DEFAULT_STATUS_READ_DELAY_MS: u64 = 30;
+const DEFAULT_LOW_RSSI_DBM: i16 = -85;
+
+/// Defaults read from `gan-robot.toml`, searched in the current directory and then in the user's
+/// config directory. Every field is optional; flags and environment variables take precedence.
+///
+/// There is no speed setting, as the firmware cannot set the speed, see
+/// [`lib::GanRobotController::set_speed`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub name: Option<String>,
+    pub exact_name_case: Option<bool>,
+    pub move_characteristic: Option<String>,
+    pub status_characteristic: Option<String>,
+    pub service: Option<String>,
+    pub max_sequence_len: Option<usize>,
+    pub max_moves_per_write: Option<usize>,
+    pub front: Option<String>,
+    pub write_retries: Option<usize>,
+    pub write_type: Option<String>,
+    pub status_read_delay: Option<u64>,
+    pub settle_at_end: Option<bool>,
+    pub wait_for_idle_on_connect: Option<bool>,
+    pub merge_moves: Option<bool>,
+    pub dropped_move_check: Option<String>,
+    pub low_rssi_threshold: Option<i16>,
+    pub inactivity_timeout: Option<u64>,
+    /// The duration of a quarter turn in milliseconds, see [`TimingConfig`]. Only in the file.
+    pub quarter_turn: Option<u64>,
+    /// The duration of a double turn in milliseconds, see [`TimingConfig`]. Only in the file.
+    pub double_turn: Option<u64>,
+    pub timezone: Option<String>,
+    /// Move sequences by name, for `run <name>` in the REPL.
+    pub sequences: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// Loads the first config file found, or the empty config if there is none.
+    pub fn load() -> anyhow::Result<Self> {
+        let candidates = [
+            Some(PathBuf::from(CONFIG_FILE_NAME)),
+            dirs::config_dir().map(|dir| dir.join(CONFIG_FILE_NAME)),
+        ];
+        match candidates.into_iter().flatten().find(|path| path.is_file()) {
+            Some(path) => toml::from_str(&fs::read_to_string(&path)?)
+                .map_err(|e| anyhow::anyhow!("Invalid config file {}: {e}", path.display())),
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+/// The settings the controller is built with, after resolving the flags against the config file,
+/// see [`resolve`].
+#[derive(Debug, PartialEq)]
+pub struct Settings {
+    pub name: String,
+    pub exact_name_case: bool,
+    pub move_characteristic: String,
+    pub status_characteristic: String,
+    pub service: String,
+    pub max_sequence_len: Option<usize>,
+    pub max_moves_per_write: usize,
+    pub front: Orientation,
+    pub write_retries: usize,
+    pub write_type: WriteType,
+    pub status_read_delay: Duration,
+    pub settle_at_end: bool,
+    pub wait_for_idle_on_connect: bool,
+    pub merge_moves: bool,
+    pub dropped_move_check: DroppedMoveCheck,
+    pub low_rssi_threshold: i16,
+    pub inactivity_timeout: Option<Duration>,
+    pub timing: TimingConfig,
+    pub timezone: String,
+}
+
+/// Resolves each setting from the flag or environment variable in `args`, then the value in
+/// `config`, then the built-in default. Flags without a value can only turn a setting on, so a
+/// setting turned on in the file stays on.
+pub fn resolve(args: &Args, config: &Config) -> anyhow::Result<Settings> {
+    let string = |arg: &Option<String>, file: &Option<String>, default: &str| {
+        arg.clone().or(file.clone()).unwrap_or(default.to_string())
+    };
+    let flag = |arg: bool, file: Option<bool>| arg || file.unwrap_or(false);
+    let front = match (args.front, &config.front) {
+        (Some(front), _) => front,
+        (None, Some(front)) => front.parse()?,
+        (None, None) => Orientation::default(),
+    };
+    let write_type = match (args.write_type, &config.write_type) {
+        (Some(write_type), _) => write_type,
+        (None, Some(write_type)) => parse_write_type(write_type).map_err(anyhow::Error::msg)?,
+        (None, None) => WriteType::WithoutResponse,
+    };
+    let dropped_move_check = match (args.dropped_move_check, &config.dropped_move_check) {
+        (Some(dropped_move_check), _) => dropped_move_check,
+        (None, Some(dropped_move_check)) => dropped_move_check.parse()?,
+        (None, None) => DroppedMoveCheck::default(),
+    };
+    let mut timing = TimingConfig::default();
+    if let Some(ms) = config.quarter_turn {
+        timing.quarter_turn = Duration::from_millis(ms);
+    }
+    if let Some(ms) = config.double_turn {
+        timing.double_turn = Duration::from_millis(ms);
+    }
+    Ok(Settings {
+        name: string(&args.name, &config.name, DEFAULT_NAME),
+        exact_name_case: flag(args.exact_name_case, config.exact_name_case),
+        move_characteristic: string(
+            &args.move_characteristic,
+            &config.move_characteristic,
+            DEFAULT_MOVE_CHARACTERISTIC,
+        ),
+        status_characteristic: string(
+            &args.status_characteristic,
+            &config.status_characteristic,
+            DEFAULT_STATUS_CHARACTERISTIC,
+        ),
+        service: string(&args.service, &config.service, GAN_ROBOT_SERVICE),
+        max_sequence_len: args.max_sequence_len.or(config.max_sequence_len),
+        max_moves_per_write: args
+            .max_moves_per_write
+            .or(config.max_moves_per_write)
+            .unwrap_or(MAX_MOVES_PER_WRITE),
+        front,
+        write_retries: args.write_retries.or(config.write_retries).unwrap_or(0),
+        write_type,
+        status_read_delay: Duration::from_millis(
+            args.status_read_delay
+                .or(config.status_read_delay)
+                .unwrap_or(DEFAULT_STATUS_READ_DELAY_MS),
+        ),
+        settle_at_end: flag(args.settle_at_end, config.settle_at_end),
+        wait_for_idle_on_connect: flag(
+            args.wait_for_idle_on_connect,
+            config.wait_for_idle_on_connect,
+        ),
+        merge_moves: flag(args.merge_moves, config.merge_moves),
+        dropped_move_check,
+        low_rssi_threshold: args
+            .low_rssi_threshold
+            .or(config.low_rssi_threshold)
+            .unwrap_or(DEFAULT_LOW_RSSI_DBM),
+        inactivity_timeout: args
+            .inactivity_timeout
+            .or(config.inactivity_timeout)
+            .map(Duration::from_secs),
+        timing,
+        timezone: string(&args.timezone, &config.timezone, DEFAULT_TIMEZONE),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn args(flags: &[&str]) -> Args {
+        let command = ["gan-robot-controller"].iter().chain(flags).chain(&["status"]);
+        Args::try_parse_from(command).unwrap()
+    }
+
+    #[test]
+    fn defaults_apply_without_flags_or_file() {
+        let settings = resolve(&args(&[]), &Config::default()).unwrap();
+        assert_eq!(settings.name, DEFAULT_NAME);
+        assert_eq!(settings.max_moves_per_write, MAX_MOVES_PER_WRITE);
+        assert_eq!(settings.front, Orientation::FrontF);
+        assert_eq!(settings.write_type, WriteType::WithoutResponse);
+        assert_eq!(settings.status_read_delay, Duration::from_millis(30));
+        assert_eq!(settings.dropped_move_check, DroppedMoveCheck::Warn);
+        assert_eq!(settings.low_rssi_threshold, -85);
+        assert_eq!(settings.timing, TimingConfig::default());
+        assert!(!settings.merge_moves);
+    }
+
+    #[test]
+    fn file_values_fill_in_and_flags_override_them() {
+        let config: Config = toml::from_str(
+            r#"
+            name = "GAN-file"
+            max-moves-per-write = 20
+            front = "R"
+            write-retries = 2
+            write-type = "response"
+            status-read-delay = 50
+            merge-moves = true
+            dropped-move-check = "error"
+            low-rssi-threshold = -75
+            quarter-turn = 180
+            "#,
+        )
+        .unwrap();
+
+        let settings = resolve(&args(&[]), &config).unwrap();
+        assert_eq!(settings.name, "GAN-file");
+        assert_eq!(settings.max_moves_per_write, 20);
+        assert_eq!(settings.front, Orientation::FrontR);
+        assert_eq!(settings.write_retries, 2);
+        assert_eq!(settings.write_type, WriteType::WithResponse);
+        assert_eq!(settings.status_read_delay, Duration::from_millis(50));
+        assert!(settings.merge_moves);
+        assert_eq!(settings.dropped_move_check, DroppedMoveCheck::Error);
+        assert_eq!(settings.low_rssi_threshold, -75);
+        assert_eq!(settings.timing.quarter_turn, Duration::from_millis(180));
+        assert_eq!(settings.timing.double_turn, TimingConfig::default().double_turn);
+
+        let flags = args(&[
+            "--name",
+            "GAN-flag",
+            "--max-moves-per-write",
+            "10",
+            "--front",
+            "B",
+            "--write-retries",
+            "0",
+            "--write-type",
+            "no-response",
+            "--status-read-delay",
+            "10",
+            "--dropped-move-check",
+            "off",
+            "--low-rssi-threshold",
+            "-90",
+        ]);
+        let settings = resolve(&flags, &config).unwrap();
+        assert_eq!(settings.name, "GAN-flag");
+        assert_eq!(settings.max_moves_per_write, 10);
+        assert_eq!(settings.front, Orientation::FrontB);
+        assert_eq!(settings.write_retries, 0);
+        assert_eq!(settings.write_type, WriteType::WithoutResponse);
+        assert_eq!(settings.status_read_delay, Duration::from_millis(10));
+        assert_eq!(settings.dropped_move_check, DroppedMoveCheck::Off);
+        assert_eq!(settings.low_rssi_threshold, -90);
+    }
+
+    #[test]
+    fn invalid_file_values_are_rejected() {
+        let config = Config { front: Some("U".to_string()), ..Config::default() };
+        let e = resolve(&args(&[]), &config).unwrap_err();
+        assert_eq!(e.to_string(), "Invalid orientation: U. Must be F, R, B or L");
+    }
+}
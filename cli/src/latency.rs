@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use lib::{Connected, GanRobotController};
+use tokio::time::Instant;
+
+const HISTOGRAM_WIDTH: usize = 40;
+
+/// Reads the status `samples` times and prints the minimum, average, median and maximum round
+/// trip time with a histogram, to compare Bluetooth adapters. Nothing is written to the robot.
+pub async fn latency(
+    controller: &GanRobotController<Connected>,
+    samples: usize,
+) -> anyhow::Result<()> {
+    if samples == 0 {
+        anyhow::bail!("Invalid number of samples: 0. Must be at least 1");
+    }
+    let mut times = Vec::with_capacity(samples);
+    for _ in 0..samples {
+        let start = Instant::now();
+        controller.status().await?;
+        times.push(start.elapsed());
+    }
+    times.sort();
+
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let average = times.iter().sum::<Duration>() / samples as u32;
+    println!("Samples: {samples}");
+    println!("Min: {:.1} ms", ms(times[0]));
+    println!("Avg: {:.1} ms", ms(average));
+    println!("Median: {:.1} ms", ms(times[samples / 2]));
+    println!("Max: {:.1} ms", ms(times[samples - 1]));
+
+    // Ten buckets of equal width between the fastest and the slowest read.
+    let (min, max) = (ms(times[0]), ms(times[samples - 1]));
+    let width = ((max - min) / 10.0).max(0.1);
+    let mut buckets = [0usize; 10];
+    for &t in &times {
+        buckets[(((ms(t) - min) / width) as usize).min(9)] += 1;
+    }
+    let most = buckets.iter().copied().max().unwrap_or(1);
+    println!();
+    for (i, &count) in buckets.iter().enumerate() {
+        let from = min + width * i as f64;
+        let bar = "#".repeat((count * HISTOGRAM_WIDTH).div_ceil(most));
+        println!("{from:>7.1} ms | {bar} {count}");
+    }
+    Ok(())
+}
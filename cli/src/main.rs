@@ -1,43 +1,140 @@
+mod benchmark;
+mod config;
+mod latency;
+mod roundtrip;
+mod selftest;
+mod serve;
+
 use std::{
-    io::{stdin, Write},
-    sync::LazyLock,
+    collections::BTreeMap,
+    io::{IsTerminal, Write},
+    path::PathBuf,
+    pin::pin,
+    time::Duration,
 };
 
+use btleplug::api::Peripheral;
 use clap::Parser;
 use env_logger::{
     fmt::style::{AnsiColor, Style},
     Builder, Env,
 };
+use futures::StreamExt;
 use jiff::{tz::TimeZone, Zoned};
-use lib::{FaceRotation, GanRobotController, MAX_MOVES_PER_WRITE};
-use log::info;
+use lib::{
+    cancel_notation, decode_frame, import_tnoodle, is_identity, parse_moves_translating_u,
+    to_written, CompletionPredicate, Connected, CsvMoveLog, DroppedMoveCheck, FaceRotation,
+    GanRobotController, Orientation, ServiceInfo, Uninitialized, WriteType, MAX_MOVES_PER_WRITE,
+};
+use log::{info, warn};
+use tokio::{
+    io::{stdin, AsyncBufReadExt, BufReader},
+    signal,
+};
 
-static TZ: LazyLock<TimeZone> = LazyLock::new(|| TimeZone::get("Asia/Tokyo").unwrap());
+use crate::{
+    benchmark::OutputFormat,
+    config::{Config, Settings},
+};
 
+// Each option is resolved in the order of the flag, the environment variable, the value in
+// `gan-robot.toml` (searched in the current directory and then in the config directory), and the
+// built-in default.
 #[derive(Debug, Parser)]
 #[clap(version)]
 pub struct Args {
-    /// The name of the GAN robot.
-    #[arg(short, long, env = "GAN_ROBOT_NAME", default_value = "GAN-a7f13")]
-    pub name: String,
-
-    /// The move characteristic UUID of the GAN robot.
-    #[arg(
-        short,
-        long,
-        env = "GAN_ROBOT_MOVE_CHARACTERISTIC",
-        default_value = "0000fff3-0000-1000-8000-00805f9b34fb"
-    )]
-    pub move_characteristic: String,
-
-    /// The status characteristic UUID of the GAN robot.
-    #[arg(
-        short,
-        long,
-        env = "GAN_ROBOT_STATUS_CHARACTERISTIC",
-        default_value = "0000fff2-0000-1000-8000-00805f9b34fb"
-    )]
-    pub status_characteristic: String,
+    /// The name of the GAN robot [default: GAN-a7f13].
+    #[arg(short, long, env = "GAN_ROBOT_NAME")]
+    pub name: Option<String>,
+
+    /// Only match a robot whose name has the same case. By default case is ignored.
+    #[arg(long)]
+    pub exact_name_case: bool,
+
+    /// The move characteristic UUID of the GAN robot [default:
+    /// 0000fff3-0000-1000-8000-00805f9b34fb].
+    #[arg(short, long, env = "GAN_ROBOT_MOVE_CHARACTERISTIC")]
+    pub move_characteristic: Option<String>,
+
+    /// The status characteristic UUID of the GAN robot [default:
+    /// 0000fff2-0000-1000-8000-00805f9b34fb].
+    #[arg(short, long, env = "GAN_ROBOT_STATUS_CHARACTERISTIC")]
+    pub status_characteristic: Option<String>,
+
+    /// The service UUID the characteristics are expected on [default:
+    /// 0000fff0-0000-1000-8000-00805f9b34fb]. `any` searches all services.
+    #[arg(long, env = "GAN_ROBOT_SERVICE")]
+    pub service: Option<String>,
+
+    /// The maximum number of moves accepted in a single command. Unset means only the hardware
+    /// limit applies.
+    #[arg(long, env = "GAN_ROBOT_MAX_SEQUENCE_LEN")]
+    pub max_sequence_len: Option<usize>,
+
+    /// The number of moves sent in a single write, for firmware with a smaller move queue
+    /// [default: 36].
+    #[arg(long, env = "GAN_ROBOT_MAX_MOVES_PER_WRITE")]
+    pub max_moves_per_write: Option<usize>,
+
+    /// The robot face you see as the front: `F`, `R`, `B` or `L`. Moves are remapped so that
+    /// they turn the faces as you see them [default: F].
+    #[arg(long, env = "GAN_ROBOT_FRONT")]
+    pub front: Option<Orientation>,
+
+    /// How often to repeat a write that did not arrive at the robot [default: 0].
+    #[arg(long, env = "GAN_ROBOT_WRITE_RETRIES")]
+    pub write_retries: Option<usize>,
+
+    /// How moves are written: `no-response`, or `response` to have each write confirmed, which
+    /// is slower but more reliable on a flaky link [default: no-response].
+    #[arg(long, env = "GAN_ROBOT_WRITE_TYPE", value_parser = parse_write_type)]
+    pub write_type: Option<WriteType>,
+
+    /// How long to wait after a write before reading the status, in milliseconds, for robots
+    /// that report the previous remaining count for a while [default: 30].
+    #[arg(long, env = "GAN_ROBOT_STATUS_READ_DELAY")]
+    pub status_read_delay: Option<u64>,
+
+    /// Wait for sequences that fit in a single write by sleeping their estimated duration and
+    /// checking once, instead of polling the robot.
+    #[arg(long)]
+    pub settle_at_end: bool,
+
+    /// Wait for moves left from an earlier session, e.g. one that crashed, to finish when
+    /// connecting.
+    #[arg(long)]
+    pub wait_for_idle_on_connect: bool,
+
+    /// Merge consecutive moves on the same face before sending them, e.g. `R R R` into `R'`.
+    #[arg(long)]
+    pub merge_moves: bool,
+
+    /// What to do when the robot got fewer moves than were sent: `off`, `warn` or `error`
+    /// [default: warn].
+    #[arg(long, env = "GAN_ROBOT_DROPPED_MOVE_CHECK")]
+    pub dropped_move_check: Option<DroppedMoveCheck>,
+
+    /// The signal strength in dBm below which a weak signal is reported [default: -85].
+    #[arg(long, env = "GAN_ROBOT_LOW_RSSI_THRESHOLD", allow_hyphen_values = true)]
+    pub low_rssi_threshold: Option<i16>,
+
+    /// Disconnect after this many seconds without moves, e.g. so that a `serve` session does not
+    /// keep others from using a shared robot. `serve` stops when it disconnects.
+    #[arg(long, env = "GAN_ROBOT_INACTIVITY_TIMEOUT", value_name = "SECONDS")]
+    pub inactivity_timeout: Option<u64>,
+
+    /// Print each move to stdout as it is sent.
+    #[arg(long)]
+    pub echo: bool,
+
+    /// Append a row per move sent to this CSV file, with the time, face, amount and estimated
+    /// duration, e.g. to analyze practice sessions.
+    #[arg(long, value_name = "PATH")]
+    pub log_csv: Option<PathBuf>,
+
+    /// The time zone used for log timestamps [default: Asia/Tokyo].
+    #[arg(long, env = "GAN_ROBOT_TIMEZONE")]
+    pub timezone: Option<String>,
 
     #[clap(subcommand)]
     pub command: Command,
@@ -50,6 +147,16 @@ pub enum Command {
         /// The number of moves to scramble the cube with.
         #[arg(short, long, default_value = "8")]
         num: usize,
+
+        /// Scramble with the maximum number of moves and warn if `--num` is larger, instead of
+        /// failing.
+        #[arg(long)]
+        clamp: bool,
+
+        /// Write double turns as `R2` or `R2'` at random when printing the scramble, as official
+        /// scramblers do. The robot does both the same way.
+        #[arg(long)]
+        notation_variety: bool,
     },
 
     /// Do moves on the cube with the given move sequence.
@@ -57,23 +164,172 @@ pub enum Command {
         /// The move sequence to do on the cube. Each move should be separated by whitespace.
         /// Please note that the moves should be in the format of face rotation strings. Valid
         /// strings include `R`, `R'`, `R2`, `R2'`, `F`, `F'`, `F2`, `F2'`, `D`, `D'`, `D2`, `D2'`,
-        /// `L`, `L'`, `L2`, `L2'`, `B`, `B'`, `B2`, `B2'`.
-        moves: String,
+        /// `L`, `L'`, `L2`, `L2'`, `B`, `B'`, `B2`, `B2'`, and `U`, `U'`, `U2`, `U2'`, which are
+        /// done by temporarily swapping the U and D layers.
+        #[arg(required = true)]
+        moves: Vec<String>,
+
+        /// Cancel redundant moves, e.g. `R R'` or `R R R` into `R'`, without asking first.
+        #[arg(long)]
+        optimize: bool,
+    },
+
+    /// Do a classic pattern on a solved cube.
+    Pattern {
+        /// The name of the pattern: `superflip`, `checkerboard`, `cube-in-cube` or `six-spots`.
+        name: String,
+    },
+
+    /// Do a 3x3 scramble from a TNoodle export (JSON or plain text).
+    ///
+    /// U moves are translated into moves the robot can do.
+    Tnoodle {
+        /// The path to the TNoodle export.
+        path: PathBuf,
+
+        /// The number of the scramble to do, starting from 1.
+        #[arg(short, long, default_value = "1")]
+        scramble: usize,
+    },
+
+    /// Do all 3x3 scrambles from a TNoodle export one after another.
+    Batch {
+        /// The path to the TNoodle export.
+        path: PathBuf,
+    },
+
+    /// Undo each scramble of a text file, one per line, by doing its inverse.
+    ///
+    /// Lines that do not parse are reported and skipped.
+    SolveFile {
+        /// The path to the file.
+        path: PathBuf,
+
+        /// Fail before doing anything if a line does not parse.
+        #[arg(long)]
+        strict: bool,
+    },
+
+    /// Keep doing random moves at the given interval until Ctrl-C is pressed.
+    Wander {
+        /// The interval between moves in milliseconds.
+        #[arg(short, long, default_value = "1000")]
+        interval: u64,
     },
 
+    /// Show the signal strength of the robot until Ctrl-C is pressed.
+    Rssi {
+        /// The interval between readings in milliseconds.
+        #[arg(short, long, default_value = "1000")]
+        interval: u64,
+    },
+
+    /// Show the remaining moves and the signal strength of the robot.
+    Status,
+
+    /// List the services and characteristics of the robot, e.g. to find the UUIDs of another
+    /// model. This works even if the configured characteristics are wrong.
+    Gatt,
+
+    /// Do a fixed sequence and report how fast the robot is compared to the estimate.
+    ///
+    /// Each round is 30 moves on all faces that cancel out, so the cube ends up as it was.
+    Benchmark {
+        /// The number of rounds to do.
+        #[arg(short, long, default_value = "2")]
+        rounds: usize,
+
+        /// The output format: `human` or `json`.
+        #[arg(long, value_enum, default_value = "human")]
+        output: OutputFormat,
+    },
+
+    /// Measure how long status reads take, to compare Bluetooth adapters.
+    ///
+    /// Prints the minimum, average, median and maximum round trip time and a histogram. Nothing
+    /// is written to the robot.
+    Latency {
+        /// The number of reads to time.
+        #[arg(short, long, default_value = "20")]
+        samples: usize,
+    },
+
+    /// Do `R R' F F'` and check that the robot did every move, to verify the setup.
+    ///
+    /// Checks that the status can be read, that all moves were written and done and that they
+    /// took about as long as expected, printing `PASS` or `FAIL` for each.
+    Selftest,
+
+    /// Encode moves into the frames written to the robot, decode them back and check that they
+    /// match, without connecting.
+    ///
+    /// Prints the frames as hex. Double turns like `R2'` are encoded as `R2`, as the protocol
+    /// does not tell them apart.
+    Roundtrip {
+        /// The moves to encode, e.g. `"R F' L2"`.
+        #[arg(required = true)]
+        moves: Vec<String>,
+    },
+
+    /// Write frames given as hex on stdin to the robot as they are, one per line.
+    ///
+    /// A frame is at most 18 bytes with the moves packed two per byte and terminated by a `0xf`
+    /// nibble, e.g. `048f` for `R F2 D'`. Malformed frames are rejected before anything is sent.
+    RawBytes,
+
     /// Enter a REPL to interact with the cube.
+    ///
+    /// Each line is a move sequence, or `run <name>` to do a sequence from the `[sequences]`
+    /// table of the config file.
     Repl {
         /// Use raw u8 values for moves instead of the default face rotation strings like "R",
         /// "R2", "R'".
         #[arg(short, long)]
         debug: bool,
     },
+
+    /// Accept commands from other programs on a socket until Ctrl-C is pressed.
+    ///
+    /// Each line is a command like on the command line, e.g. `scramble -n 20` or `move R U R'`,
+    /// and gets a reply line starting with `ok` or `error`. Commands from several clients run
+    /// one at a time. `wander`, `rssi`, `repl`, `raw-bytes`, `serve`, `gatt`, `benchmark`,
+    /// `selftest`, `latency`, `roundtrip` and `chain` are not available.
+    Serve {
+        /// The TCP address to listen on, or `unix:<path>` for a Unix socket.
+        #[arg(short, long, default_value = "127.0.0.1:7878")]
+        address: String,
+    },
+
+    /// Run several commands one after another on a single connection.
+    ///
+    /// Each argument is a command like on the command line, e.g. `chain "scramble -n 20" "move
+    /// R U R'" status`. All commands are checked before connecting, and the chain stops at the
+    /// first one that fails.
+    Chain {
+        /// The commands to run.
+        #[arg(required = true)]
+        commands: Vec<String>,
+    },
+}
+
+impl Command {
+    /// Parses a single command given as a line, as if its words were given after
+    /// `gan-robot-controller` on the command line.
+    pub fn parse_line(line: &str) -> Result<Self, clap::Error> {
+        Self::try_parse_from(std::iter::once("gan-robot-controller").chain(line.split_whitespace()))
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let config = Config::load()?;
+    let settings = config::resolve(&args, &config)?;
+    let Args { echo, log_csv, command, .. } = args;
+    let tz = TimeZone::get(&settings.timezone)?;
+
     Builder::from_env(Env::default().default_filter_or("info"))
-        .format(|buf, record| {
+        .format(move |buf, record| {
             let subtle = Style::new().fg_color(Some(AnsiColor::BrightBlack.into()));
             let level_style = buf.default_level_style(record.level());
 
@@ -81,7 +337,7 @@ async fn main() -> anyhow::Result<()> {
                 buf,
                 "{subtle}[{subtle:#}{} {level_style}{:<5}{level_style:#}{subtle}]{subtle:#} {}",
                 Zoned::now()
-                    .with_time_zone(TZ.clone())
+                    .with_time_zone(tz.clone())
                     .strftime("%Y-%m-%d %H:%M:%S %:z"),
                 record.level(),
                 record.args()
@@ -89,60 +345,493 @@ async fn main() -> anyhow::Result<()> {
         })
         .init();
 
-    let Args {
-        name,
-        move_characteristic,
-        status_characteristic,
-        command,
-    } = Args::parse();
-    let controller =
-        GanRobotController::try_new(&name, &move_characteristic, &status_characteristic)?
-            .try_connect()
-            .await?;
+    if let Command::Roundtrip { moves } = &command {
+        return roundtrip::roundtrip(&moves.join(" "));
+    }
+    let mut builder = controller_builder(&settings)?;
+    let csv_log = log_csv
+        .map(|path| anyhow::Ok(CsvMoveLog::create(path)?.with_timing(settings.timing.clone())))
+        .transpose()?;
+    if echo || csv_log.is_some() {
+        builder = builder.with_on_move_sent(move |m| {
+            if echo {
+                println!("{m}");
+            }
+            if let Some(csv_log) = &csv_log {
+                csv_log.log(m);
+            }
+        });
+    }
+    if let Command::Gatt = command {
+        print_gatt(&builder.try_describe_gatt().await?);
+        return Ok(());
+    }
+    let commands = match command {
+        Command::Chain { commands } => commands
+            .iter()
+            .map(|line| match Command::parse_line(line) {
+                Ok(Command::Chain { .. }) => anyhow::bail!("A chain cannot contain another chain"),
+                Ok(command) => Ok(command),
+                Err(e) => Err(anyhow::anyhow!("Invalid command in chain: {line}\n{e}")),
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?,
+        command => vec![command],
+    };
+    let controller = builder.try_connect().await?;
+
+    let interactive = std::io::stdin().is_terminal();
+    let run_all = async {
+        for command in commands {
+            run(&controller, command, interactive, &config.sequences).await?;
+        }
+        anyhow::Ok(())
+    };
+    let result = tokio::select! {
+        result = run_all => result,
+        _ = signal::ctrl_c() => {
+            info!("Interrupted");
+            Ok(())
+        }
+    };
+
+    controller.shutdown().await?;
+
+    result
+}
+
+fn print_gatt(services: &[ServiceInfo]) {
+    for service in services {
+        println!("Service {}", service.uuid);
+        for characteristic in &service.characteristics {
+            let properties = characteristic.properties.iter_names().map(|(name, _)| name);
+            println!("  {} {}", characteristic.uuid, properties.collect::<Vec<_>>().join(" | "));
+        }
+    }
+}
+
+fn parse_hex(hex: &str) -> anyhow::Result<Vec<u8>> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("Invalid hex: {hex}");
+    }
+    if !hex.len().is_multiple_of(2) {
+        anyhow::bail!("Odd number of hex digits: {hex}");
+    }
+    // Only ASCII digits are left, so every pair is a valid byte.
+    Ok((0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect())
+}
+
+fn parse_write_type(s: &str) -> Result<WriteType, String> {
+    match s {
+        "response" => Ok(WriteType::WithResponse),
+        "no-response" => Ok(WriteType::WithoutResponse),
+        _ => Err(format!("Invalid write type: {s}. Must be response or no-response")),
+    }
+}
+
+/// Returns a builder for the controller configured with `settings`.
+fn controller_builder(settings: &Settings) -> anyhow::Result<GanRobotController<Uninitialized>> {
+    let service = (settings.service != "any").then_some(settings.service.as_str());
+    Ok(GanRobotController::try_new(
+        &settings.name,
+        &settings.move_characteristic,
+        &settings.status_characteristic,
+    )?
+    .with_exact_name_case(settings.exact_name_case)
+    .with_service(service)?
+    .with_max_sequence_len(settings.max_sequence_len)
+    .with_max_moves_per_write(settings.max_moves_per_write)?
+    .with_orientation(settings.front)
+    .with_write_retries(settings.write_retries)
+    .with_write_type(settings.write_type)
+    .with_status_read_delay(settings.status_read_delay)
+    .with_settle_at_end(settings.settle_at_end)
+    .with_wait_for_idle_on_connect(settings.wait_for_idle_on_connect)
+    .with_merge_moves(settings.merge_moves)
+    .with_dropped_move_check(settings.dropped_move_check)
+    .with_low_rssi_threshold(settings.low_rssi_threshold)
+    .with_inactivity_timeout(settings.inactivity_timeout)
+    .with_timing(settings.timing.clone()))
+}
+
+/// Returns the remaining moves and the signal strength, as shown by the `status` command.
+async fn status(controller: &GanRobotController<Connected>) -> anyhow::Result<String> {
+    let remaining = controller.get_remaining_moves().await?;
+    let rssi = match controller.rssi().await {
+        Ok(rssi) if controller.is_weak_signal(rssi) => format!("{rssi} dBm (weak)"),
+        Ok(rssi) => format!("{rssi} dBm"),
+        Err(_) => "unknown".to_string(),
+    };
+    Ok(format!("remaining moves: {remaining}, signal strength: {rssi}"))
+}
 
+/// Runs `command`. Only if `interactive` is set, the user may be asked on stdin to choose.
+/// `sequences` are the named sequences of the config file.
+async fn run(
+    controller: &GanRobotController<Connected>,
+    command: Command,
+    interactive: bool,
+    sequences: &BTreeMap<String, String>,
+) -> anyhow::Result<()> {
     match command {
-        Command::Scramble { num } => {
-            if num > MAX_MOVES_PER_WRITE {
+        Command::Scramble { num, clamp, notation_variety } => {
+            let report = controller.scramble(scramble_len(num, clamp)?).await?;
+            info!(
+                "Scrambled with {}, estimated to take {} ms",
+                to_written(&report.moves, notation_variety)
+                    .iter()
+                    .map(|m| m.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                report.estimated.as_millis()
+            );
+        }
+        Command::Move { moves, optimize } => {
+            let moves = choose_sequence(&moves.join(" "), optimize, interactive).await?;
+            let parsed = parse_moves_translating_u(&moves);
+            if !parsed.is_empty()
+                && !parsed.contains(&FaceRotation::Invalid)
+                && is_identity(&parsed)
+            {
+                warn!("{moves} leaves the cube as it is");
+            }
+            controller.do_moves_with_u(&moves).await?;
+            controller.reorient_to_neutral().await?
+        }
+        Command::Pattern { name } => {
+            controller.set_pattern(&name).await?;
+        }
+        Command::Tnoodle { path, scramble } => {
+            let scrambles = import_tnoodle(&path)?;
+            let Some(moves) = scramble.checked_sub(1).and_then(|i| scrambles.get(i)) else {
                 anyhow::bail!(
-                    "Too many moves: {num}. Can only scramble with {MAX_MOVES_PER_WRITE} moves at a time"
+                    "Invalid scramble number: {scramble}. {} contains {} 3x3 scrambles",
+                    path.display(),
+                    scrambles.len()
                 );
-            }
-            controller.scramble(num).await?
+            };
+            controller.do_moves(moves).await?
         }
-        Command::Move { moves } => {
+        Command::Batch { path } => run_batch(controller, &import_tnoodle(&path)?).await?,
+        Command::SolveFile { path, strict } => {
+            let solved = controller.do_inverse_scramble_file(&path, strict).await?;
+            info!("Undid {solved} scrambles");
+        }
+        Command::Wander { interval } => {
             controller
-                .do_moves(&moves.split_whitespace().map(FaceRotation::from).collect::<Vec<_>>())
+                .wander(Duration::from_millis(interval), async {
+                    let _ = signal::ctrl_c().await;
+                })
                 .await?
         }
+        Command::Rssi { interval } => {
+            let mut rssi = pin!(controller.rssi_stream(Duration::from_millis(interval)));
+            while let Some(rssi) = rssi.next().await {
+                info!("Signal strength: {} dBm", rssi?);
+            }
+        }
+        Command::Status => info!("{}", status(controller).await?),
+        Command::Gatt => print_gatt(&controller.describe_gatt().await),
         Command::Repl { debug } => {
             info!("Entering REPL. Type `exit` to exit.");
-            loop {
-                let mut input = String::new();
-                stdin().read_line(&mut input)?;
+            let mut lines = BufReader::new(stdin()).lines();
+            while let Some(input) = lines.next_line().await? {
                 let input = input.trim();
 
                 if input == "exit" {
                     break;
                 }
 
-                if debug {
-                    let moves = input
-                        .split_whitespace()
-                        .map(|s| s.parse::<u8>().unwrap_or_default())
-                        .collect::<Vec<_>>();
-                    controller.do_moves_raw(&moves).await?;
-                } else {
-                    controller
-                        .do_moves(
-                            &input.split_whitespace().map(FaceRotation::from).collect::<Vec<_>>(),
-                        )
-                        .await?;
+                match ReplAction::parse(input, debug, sequences) {
+                    Ok(action) => action.run(controller).await?,
+                    Err(e) => warn!("{e}"),
+                }
+            }
+            controller.reorient_to_neutral().await?
+        }
+        Command::RawBytes => {
+            let mut lines = BufReader::new(stdin()).lines();
+            let mut number = 0;
+            while let Some(line) = lines.next_line().await? {
+                number += 1;
+                let hex = line.split_whitespace().collect::<String>();
+                if hex.is_empty() {
+                    continue;
                 }
+                let moves = parse_hex(&hex)
+                    .and_then(|bytes| decode_frame(&bytes))
+                    .map_err(|e| anyhow::anyhow!("Line {number}: {e}"))?;
+                controller.do_moves_raw(&moves, CompletionPredicate::Drained).await?;
             }
         }
+        Command::Serve { address } => serve::serve(controller, &address).await?,
+        Command::Benchmark { rounds, output } => {
+            benchmark::benchmark(controller, rounds, output).await?
+        }
+        Command::Selftest => selftest::selftest(controller).await?,
+        Command::Latency { samples } => latency::latency(controller, samples).await?,
+        Command::Roundtrip { moves } => roundtrip::roundtrip(&moves.join(" "))?,
+        // Chains are split up before connecting.
+        Command::Chain { .. } => anyhow::bail!("A chain cannot contain another chain"),
+    }
+
+    Ok(())
+}
+
+/// What a line typed into the REPL asks for.
+#[derive(Debug, PartialEq)]
+enum ReplAction<'a> {
+    /// Moves in standard notation, including U moves.
+    Moves(&'a str),
+    /// A sequence from the config, by `run <name>`.
+    Named { name: &'a str, moves: &'a str },
+    /// Protocol values, in debug mode.
+    Raw(Vec<u8>),
+}
+
+impl<'a> ReplAction<'a> {
+    /// Parses a trimmed line other than `exit`. Fails for unknown sequence names and, in debug
+    /// mode, for values that are not numbers, which the REPL warns about.
+    fn parse(
+        input: &'a str,
+        debug: bool,
+        sequences: &'a BTreeMap<String, String>,
+    ) -> anyhow::Result<Self> {
+        if let Some(name) = input.strip_prefix("run ") {
+            let name = name.trim();
+            return Ok(Self::Named { name, moves: named_sequence(sequences, name)? });
+        }
+        if debug {
+            let moves = input
+                .split_whitespace()
+                .map(|s| s.parse::<u8>().map_err(|_| anyhow::anyhow!("Invalid move: {s}")));
+            return Ok(Self::Raw(moves.collect::<anyhow::Result<_>>()?));
+        }
+        Ok(Self::Moves(input))
+    }
+
+    async fn run<P: Peripheral + 'static>(
+        self,
+        controller: &GanRobotController<Connected<P>>,
+    ) -> anyhow::Result<()> {
+        match self {
+            Self::Moves(moves) => controller.do_moves_with_u(moves).await,
+            Self::Named { name, moves } => {
+                info!("Running {name}: {moves}");
+                controller.do_moves_with_u(moves).await
+            }
+            // Values above 14 are rejected by the controller before anything is sent.
+            Self::Raw(moves) => controller.do_moves_raw(&moves, CompletionPredicate::Drained).await,
+        }
+    }
+}
+
+/// Returns the moves of the sequence called `name` in the config, or an error listing the names
+/// there are.
+fn named_sequence<'a>(
+    sequences: &'a BTreeMap<String, String>,
+    name: &str,
+) -> anyhow::Result<&'a str> {
+    match sequences.get(name) {
+        Some(moves) => Ok(moves),
+        None if sequences.is_empty() => {
+            anyhow::bail!("Unknown sequence: {name}. No sequences are configured")
+        }
+        None => anyhow::bail!(
+            "Unknown sequence: {name}. Must be one of {}",
+            sequences.keys().cloned().collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
+
+/// Returns the number of moves to scramble with for `--num`, which is at most
+/// [`MAX_MOVES_PER_WRITE`]. Larger numbers are clamped with a warning if `clamp` is set.
+fn scramble_len(num: usize, clamp: bool) -> anyhow::Result<usize> {
+    if num <= MAX_MOVES_PER_WRITE {
+        return Ok(num);
     }
+    if !clamp {
+        anyhow::bail!(
+            "Too many moves: {num}. Can only scramble with {MAX_MOVES_PER_WRITE} moves at a time"
+        );
+    }
+    warn!("Too many moves: {num}. Scrambling with {MAX_MOVES_PER_WRITE} moves instead");
+    Ok(MAX_MOVES_PER_WRITE)
+}
 
-    controller.disconnect().await?;
+/// Returns `moves` with redundant moves cancelled if there are any and `optimize` is set or the
+/// user agrees when asked, and `moves` as they are otherwise.
+async fn choose_sequence(moves: &str, optimize: bool, interactive: bool) -> anyhow::Result<String> {
+    let optimized = cancel_notation(moves)?;
+    let before = moves.split_whitespace().count();
+    let after = optimized.split_whitespace().count();
+    if after == before {
+        return Ok(moves.to_string());
+    }
+    info!("Original:  {moves} ({before} moves)");
+    info!("Optimized: {optimized} ({after} moves, {} cancelled)", before - after);
+    let use_optimized = if optimize {
+        true
+    } else if interactive {
+        eprint!("Do the optimized sequence? [Y/n] ");
+        let answer = BufReader::new(stdin()).lines().next_line().await?.unwrap_or_default();
+        !answer.trim().eq_ignore_ascii_case("n")
+    } else {
+        info!("Doing the original sequence. Pass --optimize to do the optimized one");
+        false
+    };
+    Ok(if use_optimized { optimized } else { moves.to_string() })
+}
 
+async fn run_batch(
+    controller: &GanRobotController<Connected>,
+    scrambles: &[Vec<FaceRotation>],
+) -> anyhow::Result<()> {
+    let total = scrambles
+        .iter()
+        .map(|s| controller.estimated_duration(s))
+        .sum::<Duration>();
+    info!("Doing {} scrambles, estimated to take {} s", scrambles.len(), total.as_secs());
+    let progress = Progress::new(total)?;
+    for (i, moves) in scrambles.iter().enumerate() {
+        info!("Scramble {}/{}", i + 1, scrambles.len());
+        controller
+            .do_moves_with_progress(moves, |j| {
+                progress.inc(controller.estimated_duration(&moves[j..=j]))
+            })
+            .await?;
+    }
+    progress.finish();
     Ok(())
 }
+
+/// A progress bar over the estimated duration, shown only with the `progress` feature. The bar
+/// hides itself when stderr is not a terminal.
+struct Progress {
+    #[cfg(feature = "progress")]
+    bar: indicatif::ProgressBar,
+}
+
+impl Progress {
+    #[cfg(feature = "progress")]
+    fn new(total: Duration) -> anyhow::Result<Self> {
+        let bar = indicatif::ProgressBar::new(total.as_millis() as u64).with_style(
+            indicatif::ProgressStyle::with_template("{wide_bar} {percent:>3}% ETA {eta}")?,
+        );
+        Ok(Self { bar })
+    }
+
+    #[cfg(not(feature = "progress"))]
+    fn new(_total: Duration) -> anyhow::Result<Self> {
+        Ok(Self {})
+    }
+
+    fn inc(&self, _duration: Duration) {
+        #[cfg(feature = "progress")]
+        self.bar.inc(_duration.as_millis() as u64);
+    }
+
+    fn finish(&self) {
+        #[cfg(feature = "progress")]
+        self.bar.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lib::{CharPropFlags, FakePeripheral, RecordingSleeper};
+
+    use super::*;
+    use crate::config::{DEFAULT_MOVE_CHARACTERISTIC, DEFAULT_NAME, DEFAULT_STATUS_CHARACTERISTIC};
+
+    #[test]
+    fn scramble_len_is_capped_at_a_single_write() {
+        assert_eq!(scramble_len(20, false).unwrap(), 20);
+        assert_eq!(scramble_len(MAX_MOVES_PER_WRITE, false).unwrap(), MAX_MOVES_PER_WRITE);
+        let e = scramble_len(37, false).unwrap_err();
+        assert_eq!(e.to_string(), "Too many moves: 37. Can only scramble with 36 moves at a time");
+        assert_eq!(scramble_len(37, true).unwrap(), MAX_MOVES_PER_WRITE);
+    }
+
+    #[test]
+    fn named_sequences_are_looked_up_in_the_config() {
+        let e = named_sequence(&BTreeMap::new(), "sexy").unwrap_err();
+        assert_eq!(e.to_string(), "Unknown sequence: sexy. No sequences are configured");
+
+        let sequences = BTreeMap::from([
+            ("sune".to_string(), "R U R' U R U2 R'".to_string()),
+            ("sexy".to_string(), "R U R' U'".to_string()),
+        ]);
+        assert_eq!(named_sequence(&sequences, "sexy").unwrap(), "R U R' U'");
+        let e = named_sequence(&sequences, "t-perm").unwrap_err();
+        assert_eq!(e.to_string(), "Unknown sequence: t-perm. Must be one of sexy, sune");
+    }
+
+    #[tokio::test]
+    async fn repl_run_does_the_configured_sequence() {
+        let robot = FakePeripheral::new(DEFAULT_MOVE_CHARACTERISTIC, DEFAULT_STATUS_CHARACTERISTIC)
+            .unwrap();
+        robot.push_read(robot.status_characteristic(), vec![0]);
+        let controller = GanRobotController::try_new(
+            DEFAULT_NAME,
+            DEFAULT_MOVE_CHARACTERISTIC,
+            DEFAULT_STATUS_CHARACTERISTIC,
+        )
+        .unwrap()
+        .with_dropped_move_check(DroppedMoveCheck::Off)
+        .with_sleeper(RecordingSleeper::new())
+        .from_peripheral(robot.clone())
+        .await
+        .unwrap();
+        let sequences = BTreeMap::from([("sexy".to_string(), "R F R' F'".to_string())]);
+
+        let action = ReplAction::parse("run sexy", false, &sequences).unwrap();
+        assert_eq!(action, ReplAction::Named { name: "sexy", moves: "R F R' F'" });
+        action.run(&controller).await.unwrap();
+        let writes = robot.writes(robot.move_characteristic());
+        assert_eq!(decode_frame(&writes[0]).unwrap(), [0, 3, 2, 5]);
+
+        let e = ReplAction::parse("run sune", false, &sequences).unwrap_err();
+        assert_eq!(e.to_string(), "Unknown sequence: sune. Must be one of sexy");
+        assert_eq!(robot.writes(robot.move_characteristic()).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn the_write_type_flag_reaches_the_controller() {
+        let move_characteristic = "0000fff5-0000-1000-8000-00805f9b34fb";
+        let uuid = uuid::Uuid::parse_str(move_characteristic).unwrap();
+        for (flag, write_type) in
+            [("response", WriteType::WithResponse), ("no-response", WriteType::WithoutResponse)]
+        {
+            let robot =
+                FakePeripheral::new(DEFAULT_MOVE_CHARACTERISTIC, DEFAULT_STATUS_CHARACTERISTIC)
+                    .unwrap();
+            let properties = CharPropFlags::WRITE | CharPropFlags::WRITE_WITHOUT_RESPONSE;
+            robot.add_characteristic(move_characteristic, properties).unwrap();
+            robot.push_read(robot.status_characteristic(), vec![0]);
+            let args = Args::try_parse_from([
+                "gan-robot-controller",
+                "--move-characteristic",
+                move_characteristic,
+                "--write-type",
+                flag,
+                "--dropped-move-check",
+                "off",
+                "status",
+            ])
+            .unwrap();
+            let settings = config::resolve(&args, &Config::default()).unwrap();
+            let controller = controller_builder(&settings)
+                .unwrap()
+                .with_sleeper(RecordingSleeper::new())
+                .from_peripheral(robot.clone())
+                .await
+                .unwrap();
+
+            controller.do_moves(&[FaceRotation::R]).await.unwrap();
+            assert_eq!(robot.write_types(uuid), [write_type]);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use lib::{decode_frame, encode_frames, FaceRotation, WrittenRotation};
+
+/// The moves given to [`roundtrip`], the frames they encode to and the moves decoded from them.
+struct Roundtrip {
+    written: Vec<WrittenRotation>,
+    frames: Vec<Vec<u8>>,
+    decoded: Vec<FaceRotation>,
+}
+
+impl Roundtrip {
+    /// Returns `true` if the decoded moves are the ones given, up to the direction of double
+    /// turns.
+    fn matches(&self) -> bool {
+        self.decoded.iter().eq(self.written.iter().map(|m| &m.rotation))
+    }
+}
+
+/// Encodes `moves` into frames as written to the robot, decodes them back and prints both,
+/// followed by whether the decoded moves match. Fails if the moves do not parse or do not match.
+pub fn roundtrip(moves: &str) -> anyhow::Result<()> {
+    let roundtrip = encode_and_decode(moves)?;
+
+    let join = |moves: Vec<String>| moves.join(" ");
+    println!("Moves: {}", join(roundtrip.written.iter().map(|m| m.to_string()).collect()));
+    for (i, frame) in roundtrip.frames.iter().enumerate() {
+        let hex = frame.iter().map(|b| format!("{b:02x}")).collect::<String>();
+        println!("Frame {}: {hex}", i + 1);
+    }
+    println!("Decoded: {}", join(roundtrip.decoded.iter().map(|m| m.to_string()).collect()));
+
+    // The protocol has a single value per double turn, so the direction is lost.
+    if roundtrip.written.iter().any(|m| m.primed) {
+        println!("Note: R2' and the like are encoded as R2, which the robot does the same way");
+    }
+    if !roundtrip.matches() {
+        println!("Roundtrip: FAIL");
+        anyhow::bail!("The decoded moves do not match");
+    }
+    println!("Roundtrip: PASS");
+    Ok(())
+}
+
+fn encode_and_decode(moves: &str) -> anyhow::Result<Roundtrip> {
+    let written = moves
+        .split_whitespace()
+        .map(WrittenRotation::from)
+        .collect::<Vec<_>>();
+    if let Some(i) = written.iter().position(|m| m.rotation == FaceRotation::Invalid) {
+        let invalid = moves.split_whitespace().nth(i).unwrap_or_default();
+        if invalid.to_ascii_uppercase().starts_with('U') {
+            anyhow::bail!("Invalid move: {invalid}. The protocol has no U moves");
+        }
+        anyhow::bail!("Invalid move: {invalid}");
+    }
+
+    let frames = encode_frames(&written.iter().map(|m| m.rotation).collect::<Vec<_>>());
+    let mut decoded = vec![];
+    for frame in &frames {
+        decoded.extend(decode_frame(frame)?.into_iter().map(FaceRotation::from));
+    }
+    Ok(Roundtrip { written, frames, decoded })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_sequence_roundtrips() {
+        let roundtrip = encode_and_decode("R F' L2").unwrap();
+        assert_eq!(roundtrip.frames, [vec![0x05, 0xaf]]);
+        assert_eq!(roundtrip.decoded, [FaceRotation::R, FaceRotation::FPrime, FaceRotation::L2]);
+        assert!(roundtrip.matches());
+    }
+
+    #[test]
+    fn primed_double_turns_roundtrip_as_double_turns() {
+        let roundtrip = encode_and_decode("R2' B").unwrap();
+        assert_eq!(roundtrip.decoded, [FaceRotation::R2, FaceRotation::B]);
+        assert!(roundtrip.matches());
+    }
+
+    #[test]
+    fn full_frames_roundtrip() {
+        for len in [36, 37, 72] {
+            let roundtrip = encode_and_decode(&vec!["R"; len].join(" ")).unwrap();
+            assert_eq!(roundtrip.frames.len(), len.div_ceil(36));
+            assert_eq!(roundtrip.decoded, vec![FaceRotation::R; len]);
+        }
+    }
+
+    #[test]
+    fn invalid_moves_are_rejected() {
+        let e = encode_and_decode("R U").err().unwrap();
+        assert_eq!(e.to_string(), "Invalid move: U. The protocol has no U moves");
+        assert!(encode_and_decode("R X").is_err());
+    }
+}
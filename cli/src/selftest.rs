@@ -0,0 +1,68 @@
+use lib::{moves, Connected, GanRobotController};
+use tokio::time::Instant;
+
+/// Does a short sequence that leaves the cube as it was and checks with the status that the
+/// robot did every move in about the expected time, printing a line per check. Fails if any
+/// check does.
+pub async fn selftest(controller: &GanRobotController<Connected>) -> anyhow::Result<()> {
+    let moves = moves!("R R' F F'");
+    let mut passed = true;
+    // Prints the result of a check and returns whether all checks so far passed.
+    let mut check = |name: &str, result: Result<String, String>| {
+        match result {
+            Ok(detail) => println!("PASS {name}: {detail}"),
+            Err(detail) => {
+                println!("FAIL {name}: {detail}");
+                passed = false;
+            }
+        }
+        passed
+    };
+
+    let status = controller.status().await;
+    let idle = match &status {
+        Ok(s) if s.remaining_moves == 0 => Ok("the robot is idle".to_string()),
+        Ok(s) => Err(format!("the robot still has {} moves to do", s.remaining_moves)),
+        Err(e) => Err(format!("cannot read the status: {e}")),
+    };
+    if !check("status", idle) {
+        anyhow::bail!("Self-test failed");
+    }
+
+    let start = Instant::now();
+    let timings = controller.do_moves_timed(&moves).await;
+    let elapsed = start.elapsed();
+    let done = match &timings {
+        Ok(timings) if timings.len() == moves.len() => {
+            Ok(format!("{} moves written and done", moves.len()))
+        }
+        Ok(timings) => Err(format!("only {} of {} moves were done", timings.len(), moves.len())),
+        Err(e) => Err(format!("{e}")),
+    };
+    check("moves", done);
+
+    let remaining = match controller.status().await {
+        Ok(s) if s.remaining_moves == 0 => Ok("no moves left".to_string()),
+        Ok(s) => Err(format!("{} moves left", s.remaining_moves)),
+        Err(e) => Err(format!("cannot read the status: {e}")),
+    };
+    check("drained", remaining);
+
+    // A robot that did not turn reports the moves done right away.
+    let estimated = controller.estimated_duration(&moves);
+    let timing = if elapsed >= estimated / 2 {
+        Ok(format!("{} ms, estimated {} ms", elapsed.as_millis(), estimated.as_millis()))
+    } else {
+        Err(format!(
+            "{} ms, much faster than the estimated {} ms",
+            elapsed.as_millis(),
+            estimated.as_millis()
+        ))
+    };
+    // The last check tells whether any failed.
+    if !check("timing", timing) {
+        anyhow::bail!("Self-test failed");
+    }
+    println!("Self-test passed");
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use lib::{Connected, GanRobotController};
+use log::{info, warn};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpListener,
+    sync::Mutex,
+};
+
+use crate::{run, status, Command};
+
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener),
+}
+
+impl Listener {
+    async fn bind(address: &str) -> anyhow::Result<Self> {
+        match address.strip_prefix("unix:") {
+            #[cfg(unix)]
+            Some(path) => Ok(Self::Unix(tokio::net::UnixListener::bind(path)?)),
+            #[cfg(not(unix))]
+            Some(_) => anyhow::bail!("Unix sockets are not supported on this platform"),
+            None => Ok(Self::Tcp(TcpListener::bind(address).await?)),
+        }
+    }
+
+    async fn accept(&self) -> anyhow::Result<(Box<dyn Connection>, String)> {
+        match self {
+            Self::Tcp(listener) => {
+                let (stream, peer) = listener.accept().await?;
+                Ok((Box::new(stream), peer.to_string()))
+            }
+            #[cfg(unix)]
+            Self::Unix(listener) => {
+                let (stream, _) = listener.accept().await?;
+                Ok((Box::new(stream), "unix socket client".to_string()))
+            }
+        }
+    }
+}
+
+/// Accepts line-based commands on `address` until interrupted or disconnected for inactivity,
+/// see [`Command::Serve`]. Clients are served concurrently, but only one command runs on the
+/// robot at a time.
+pub async fn serve(
+    controller: &GanRobotController<Connected>,
+    address: &str,
+) -> anyhow::Result<()> {
+    let listener = Listener::bind(address).await?;
+    info!("Listening on {address}");
+    let robot = Mutex::new(());
+    let mut connections = FuturesUnordered::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (connection, peer) = accepted?;
+                info!("{peer} connected");
+                connections.push(handle(controller, &robot, connection, peer));
+            }
+            Some((peer, result)) = connections.next() => match result {
+                Ok(()) => info!("{peer} disconnected"),
+                Err(e) => warn!("{peer} disconnected: {e}"),
+            },
+            () = controller.disconnected_for_inactivity() => {
+                info!("Stopped serving after the inactivity timeout");
+                return Ok(());
+            }
+        }
+    }
+}
+
+async fn handle(
+    controller: &GanRobotController<Connected>,
+    robot: &Mutex<()>,
+    connection: Box<dyn Connection>,
+    peer: String,
+) -> (String, anyhow::Result<()>) {
+    let result = async {
+        let (reader, mut writer) = tokio::io::split(connection);
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line == "exit" {
+                break;
+            }
+            // Every command is logged, so that a session can be replayed from the log.
+            info!("{peer}: {line}");
+            let reply = {
+                let _robot = robot.lock().await;
+                execute(controller, line).await
+            };
+            let reply = match reply {
+                Ok(reply) => format!("ok{}{reply}", if reply.is_empty() { "" } else { " " }),
+                Err(e) => format!("error {}", e.to_string().lines().next().unwrap_or_default()),
+            };
+            writer.write_all(format!("{reply}\n").as_bytes()).await?;
+        }
+        Ok(())
+    }
+    .await;
+    (peer, result)
+}
+
+/// Runs a single line as if its words were given after `gan-robot-controller` on the command
+/// line. Commands that need the terminal or run until interrupted are rejected.
+async fn execute(controller: &GanRobotController<Connected>, line: &str) -> anyhow::Result<String> {
+    let command = Command::parse_line(line)?;
+    match command {
+        Command::Status => status(controller).await,
+        Command::Wander { .. }
+        | Command::Rssi { .. }
+        | Command::Repl { .. }
+        | Command::RawBytes
+        | Command::Serve { .. }
+        | Command::Gatt
+        | Command::Benchmark { .. }
+        | Command::Selftest
+        | Command::Latency { .. }
+        | Command::Roundtrip { .. }
+        | Command::Chain { .. } => {
+            anyhow::bail!("This command is not available over the socket")
+        }
+        // Named sequences are only used by the REPL, which is not available here.
+        command => run(controller, command, false, &BTreeMap::new())
+            .await
+            .map(|()| String::new()),
+    }
+}
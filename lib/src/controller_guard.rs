@@ -0,0 +1,106 @@
+use std::ops::Deref;
+
+use crate::logging::warn;
+use btleplug::{api, platform::Peripheral};
+use tokio::runtime::Handle;
+
+use crate::{Connected, GanRobotController};
+
+/// Owns a connected controller and makes sure it gets disconnected when going out of scope.
+///
+/// `Drop` cannot be async, so dropping the guard only spawns a detached, best-effort disconnect
+/// on the current Tokio runtime. That task does not wait for in-flight moves, and it never runs
+/// if there is no runtime or the runtime shuts down first, e.g. when `main` returns right away.
+/// Call [`Self::close`] instead whenever possible to wait for a clean disconnect.
+pub struct ControllerGuard<P: api::Peripheral + 'static = Peripheral> {
+    controller: Option<GanRobotController<Connected<P>>>,
+}
+
+impl<P: api::Peripheral + 'static> ControllerGuard<P> {
+    pub fn new(controller: GanRobotController<Connected<P>>) -> Self {
+        Self { controller: Some(controller) }
+    }
+
+    /// Waits for in-flight moves and disconnects, see
+    /// [`GanRobotController::disconnect_when_idle`].
+    pub async fn close(mut self) -> anyhow::Result<()> {
+        match self.controller.take() {
+            Some(controller) => controller.disconnect_when_idle().await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl<P: api::Peripheral + 'static> From<GanRobotController<Connected<P>>> for ControllerGuard<P> {
+    fn from(controller: GanRobotController<Connected<P>>) -> Self {
+        Self::new(controller)
+    }
+}
+
+impl<P: api::Peripheral + 'static> Deref for ControllerGuard<P> {
+    type Target = GanRobotController<Connected<P>>;
+
+    fn deref(&self) -> &Self::Target {
+        // Only `close` and `drop` take the controller, and both consume the guard.
+        self.controller.as_ref().unwrap()
+    }
+}
+
+impl<P: api::Peripheral + 'static> Drop for ControllerGuard<P> {
+    fn drop(&mut self) {
+        let Some(controller) = self.controller.take() else {
+            return;
+        };
+        match Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move {
+                    if let Err(e) = controller.disconnect().await {
+                        warn!("Failed to disconnect on drop: {e}");
+                    }
+                });
+            }
+            Err(_) => warn!("No Tokio runtime to disconnect on drop"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use btleplug::api::Peripheral as _;
+
+    use super::*;
+    use crate::{DroppedMoveCheck, FakePeripheral, RecordingSleeper};
+
+    const MOVE_UUID: &str = "0000fff3-0000-1000-8000-00805f9b34fb";
+    const STATUS_UUID: &str = "0000fff2-0000-1000-8000-00805f9b34fb";
+
+    async fn guard() -> (FakePeripheral, ControllerGuard<FakePeripheral>) {
+        let robot = FakePeripheral::new(MOVE_UUID, STATUS_UUID).unwrap();
+        robot.push_read(robot.status_characteristic(), vec![0]);
+        let controller = GanRobotController::try_new("GAN", MOVE_UUID, STATUS_UUID)
+            .unwrap()
+            .with_dropped_move_check(DroppedMoveCheck::Off)
+            .with_sleeper(RecordingSleeper::new())
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+        (robot, ControllerGuard::new(controller))
+    }
+
+    #[tokio::test]
+    async fn close_disconnects() {
+        let (robot, guard) = guard().await;
+        assert!(robot.is_connected().await.unwrap());
+        guard.close().await.unwrap();
+        assert!(!robot.is_connected().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn drop_disconnects_in_the_background() {
+        let (robot, guard) = guard().await;
+        drop(guard);
+        // The disconnect runs in a task spawned on drop.
+        tokio::task::yield_now().await;
+        assert!(!robot.is_connected().await.unwrap());
+    }
+}
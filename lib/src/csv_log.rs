@@ -0,0 +1,98 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::logging::warn;
+use crate::{FaceRotation, TimingConfig, Turn};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Appends a row per move to a CSV file, for analyzing sessions afterwards. Pass [`Self::log`]
+/// to [`crate::GanRobotController::with_on_move_sent`]. The columns are the time in
+/// milliseconds since the Unix epoch, the face, the amount in quarter turns (1, 2 or -1, as the
+/// robot does not tell double turns apart) and the estimated duration in milliseconds, see
+/// [`Self::with_timing`].
+///
+/// Rows are buffered and flushed at most a second apart and when the log is dropped. If writing
+/// fails, e.g. because the disk is full, a warning is logged once and the following moves are
+/// not logged, so that the moves themselves are not interrupted.
+pub struct CsvMoveLog {
+    path: PathBuf,
+    writer: Mutex<Option<Writer>>,
+    timing: TimingConfig,
+}
+
+struct Writer {
+    file: BufWriter<File>,
+    last_flush: Instant,
+}
+
+impl CsvMoveLog {
+    /// Opens `path` for appending, creating it with a header row if it is new or empty.
+    pub fn create(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| anyhow::anyhow!("Cannot open {} for logging: {e}", path.display()))?;
+        let is_empty = file.metadata()?.len() == 0;
+        let mut file = BufWriter::new(file);
+        if is_empty {
+            writeln!(file, "timestamp_ms,face,amount,estimated_duration_ms")?;
+        }
+        let writer = Writer { file, last_flush: Instant::now() };
+        Ok(Self {
+            path,
+            writer: Mutex::new(Some(writer)),
+            timing: TimingConfig::default(),
+        })
+    }
+
+    /// Sets how long moves are expected to take for the estimated durations, which should be the
+    /// [`TimingConfig`] of the controller. The default one is used otherwise.
+    pub fn with_timing(mut self, timing: TimingConfig) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Appends a row for `m`. Invalid moves are skipped.
+    pub fn log(&self, m: FaceRotation) {
+        let (Some(face), Some(turn)) = (m.face(), m.turn()) else {
+            return;
+        };
+        let amount = match turn {
+            Turn::Cw => 1,
+            Turn::Double => 2,
+            Turn::Ccw => -1,
+        };
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let duration = self.timing.estimated_duration(&[m]);
+
+        let mut writer = self.writer.lock().unwrap();
+        let Some(w) = writer.as_mut() else {
+            return;
+        };
+        let result = writeln!(
+            w.file,
+            "{},{face:?},{amount},{}",
+            timestamp.as_millis(),
+            duration.as_millis()
+        )
+        .and_then(|()| {
+            if w.last_flush.elapsed() < FLUSH_INTERVAL {
+                return Ok(());
+            }
+            w.last_flush = Instant::now();
+            w.file.flush()
+        });
+        if let Err(e) = result {
+            warn!("Failed to write to {}, no longer logging moves: {e}", self.path.display());
+            *writer = None;
+        }
+    }
+}
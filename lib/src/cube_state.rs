@@ -0,0 +1,290 @@
+use std::sync::LazyLock;
+
+use crate::{normalize_notation, Face, FaceRotation, Turn};
+
+/// A position on the surface of the cube, as the coordinates of the cubie and the outward normal
+/// of the sticker. The axes point to R, U and F.
+type Sticker = ([i8; 3], [i8; 3]);
+
+/// The outward normals of the faces in the order U, R, F, D, L, B.
+const NORMALS: [[i8; 3]; 6] = [[0, 1, 0], [1, 0, 0], [0, 0, 1], [0, -1, 0], [-1, 0, 0], [0, 0, -1]];
+
+static STICKERS: LazyLock<Vec<Sticker>> = LazyLock::new(|| {
+    NORMALS
+        .iter()
+        .flat_map(|&normal| {
+            let axis = normal.iter().position(|&c| c != 0).unwrap();
+            (-1..=1).flat_map(move |a| {
+                (-1..=1).map(move |b| {
+                    let mut position = [0; 3];
+                    let others = (0..3).filter(|&i| i != axis).collect::<Vec<_>>();
+                    position[axis] = normal[axis];
+                    position[others[0]] = a;
+                    position[others[1]] = b;
+                    (position, normal)
+                })
+            })
+        })
+        .collect()
+});
+
+/// For each face in the order of [`NORMALS`], where each sticker goes on a clockwise quarter turn.
+static QUARTER_TURNS: LazyLock<Vec<Vec<usize>>> = LazyLock::new(|| {
+    NORMALS
+        .iter()
+        .map(|&normal| {
+            STICKERS
+                .iter()
+                .map(|&(position, sticker_normal)| {
+                    let axis = normal.iter().position(|&c| c != 0).unwrap();
+                    if position[axis] != normal[axis] {
+                        return index_of((position, sticker_normal));
+                    }
+                    let turn = |v| rotate_clockwise(v, axis, normal[axis]);
+                    index_of((turn(position), turn(sticker_normal)))
+                })
+                .collect()
+        })
+        .collect()
+});
+
+fn index_of(sticker: Sticker) -> usize {
+    STICKERS.iter().position(|&s| s == sticker).unwrap()
+}
+
+/// Rotates `v` clockwise as seen from the face whose outward normal is `sign` on `axis`.
+fn rotate_clockwise(v: [i8; 3], axis: usize, sign: i8) -> [i8; 3] {
+    // A clockwise turn seen from the positive side is three counterclockwise ones.
+    let times = if sign > 0 { 3 } else { 1 };
+    (0..times).fold(v, |[x, y, z], _| match axis {
+        0 => [x, -z, y],
+        1 => [z, y, -x],
+        _ => [-y, x, z],
+    })
+}
+
+/// The color each face gets in [`CubeState::six_spots`] and [`CubeState::cube_in_cube`], in the
+/// order of [`NORMALS`]: U gets F, R gets U and F gets R, and the same around D, L and B.
+const SPOT_COLORS: [u8; 6] = [2, 0, 1, 5, 3, 4];
+
+/// The stickers of a 3x3 cube, to follow the effect of moves without the robot.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct CubeState {
+    /// The face each sticker originally belongs to, indexed like [`STICKERS`].
+    facelets: Vec<u8>,
+}
+
+impl Default for CubeState {
+    fn default() -> Self {
+        Self::solved()
+    }
+}
+
+impl CubeState {
+    pub fn solved() -> Self {
+        Self {
+            facelets: STICKERS.iter().map(|&(_, normal)| face_index(normal)).collect(),
+        }
+    }
+
+    /// Returns the superflip: every edge flipped in place, everything else solved. This is built
+    /// from the stickers directly rather than from moves, to check sequences against it.
+    ///
+    /// ```
+    /// use lib::{net_effect, pattern, pattern_state, CubeState};
+    ///
+    /// assert_eq!(pattern_state("superflip"), Some(CubeState::superflip()));
+    /// assert_eq!(net_effect(&pattern("superflip").unwrap()), CubeState::superflip());
+    /// ```
+    pub fn superflip() -> Self {
+        let facelets = STICKERS
+            .iter()
+            .map(|&(position, normal)| {
+                let axes = (0..3).filter(|&i| position[i] != 0).collect::<Vec<_>>();
+                let [a, b] = axes[..] else {
+                    return face_index(normal);
+                };
+                // An edge sticker gets the color of the other sticker on its cubie.
+                let other = if normal[a] != 0 { b } else { a };
+                let mut other_normal = [0; 3];
+                other_normal[other] = position[other];
+                face_index(other_normal)
+            })
+            .collect();
+        Self { facelets }
+    }
+
+    /// Returns the checkerboard: every edge sticker has the color of the opposite face, everything
+    /// else solved. Built from the stickers like [`Self::superflip`].
+    ///
+    /// ```
+    /// use lib::{net_effect, pattern, CubeState};
+    ///
+    /// assert_eq!(net_effect(&pattern("checkerboard").unwrap()), CubeState::checkerboard());
+    /// ```
+    pub fn checkerboard() -> Self {
+        Self::from_stickers(|(position, normal)| {
+            let face = face_index(normal);
+            match position.iter().filter(|&&c| c != 0).count() {
+                // Faces are ordered so that the opposite one is three further.
+                2 => (face + 3) % 6,
+                _ => face,
+            }
+        })
+    }
+
+    /// Returns six spots: every face has the color of another face except for its center. Built
+    /// from the stickers like [`Self::superflip`].
+    ///
+    /// ```
+    /// use lib::{net_effect, pattern, CubeState};
+    ///
+    /// assert_eq!(net_effect(&pattern("six-spots").unwrap()), CubeState::six_spots());
+    /// ```
+    pub fn six_spots() -> Self {
+        Self::from_stickers(|(position, normal)| {
+            let face = face_index(normal);
+            if position == normal {
+                face
+            } else {
+                SPOT_COLORS[face as usize]
+            }
+        })
+    }
+
+    /// Returns the cube in a cube: on every face, the 2x2 block at the URF or the DLB corner
+    /// keeps its color and the rest has the color of another face. Built from the stickers like
+    /// [`Self::superflip`].
+    ///
+    /// ```
+    /// use lib::{net_effect, pattern, CubeState};
+    ///
+    /// assert_eq!(net_effect(&pattern("cube-in-cube").unwrap()), CubeState::cube_in_cube());
+    /// ```
+    pub fn cube_in_cube() -> Self {
+        Self::from_stickers(|(position, normal)| {
+            let face = face_index(normal);
+            // U, R and F have a positive normal, D, L and B a negative one.
+            let sign = normal.iter().sum::<i8>();
+            if position.iter().all(|&c| c == 0 || c == sign) {
+                face
+            } else {
+                SPOT_COLORS[face as usize]
+            }
+        })
+    }
+
+    fn from_stickers(color: impl Fn(Sticker) -> u8) -> Self {
+        Self {
+            facelets: STICKERS.iter().map(|&sticker| color(sticker)).collect(),
+        }
+    }
+
+    pub fn is_solved(&self) -> bool {
+        *self == Self::solved()
+    }
+
+    /// Applies a move. Invalid moves are ignored like the controller does.
+    pub fn apply(&mut self, m: FaceRotation) {
+        let face = match m.face() {
+            Some(Face::R) => 1,
+            Some(Face::F) => 2,
+            Some(Face::D) => 3,
+            Some(Face::L) => 4,
+            Some(Face::B) => 5,
+            None => return,
+        };
+        let turns = match m.turn() {
+            Some(Turn::Cw) => 1,
+            Some(Turn::Double) => 2,
+            _ => 3,
+        };
+        self.turn(face, turns);
+    }
+
+    pub fn apply_all(&mut self, moves: &[FaceRotation]) {
+        moves.iter().for_each(|&m| self.apply(m));
+    }
+
+    /// Applies a whitespace separated sequence in standard notation, including U moves which
+    /// [`FaceRotation`] cannot express.
+    pub fn apply_notation(&mut self, s: &str) -> anyhow::Result<()> {
+        for token in normalize_notation(s)?.split_whitespace() {
+            let mut chars = token.chars();
+            let face = match chars.next().map(|c| c.to_ascii_uppercase()) {
+                Some('U') => 0,
+                Some('R') => 1,
+                Some('F') => 2,
+                Some('D') => 3,
+                Some('L') => 4,
+                Some('B') => 5,
+                _ => anyhow::bail!("Invalid move: {token}"),
+            };
+            let turns = match chars.as_str() {
+                "" => 1,
+                "2" | "2'" => 2,
+                "'" => 3,
+                _ => anyhow::bail!("Invalid move: {token}"),
+            };
+            self.turn(face, turns);
+        }
+        Ok(())
+    }
+
+    fn turn(&mut self, face: usize, turns: usize) {
+        for _ in 0..turns {
+            let mut facelets = self.facelets.clone();
+            QUARTER_TURNS[face].iter().enumerate().for_each(|(from, &to)| {
+                facelets[to] = self.facelets[from];
+            });
+            self.facelets = facelets;
+        }
+    }
+}
+
+/// Returns the state a solved cube is in after `moves`.
+pub fn net_effect(moves: &[FaceRotation]) -> CubeState {
+    let mut state = CubeState::solved();
+    state.apply_all(moves);
+    state
+}
+
+/// Returns `true` if `moves` leave a cube as it was, e.g. to warn that a sequence does nothing.
+///
+/// ```
+/// use lib::{is_identity, moves};
+///
+/// assert!(is_identity(&moves!("R R'")));
+/// assert!(is_identity(&moves!("R U R' U'").repeat(6)));
+/// assert!(!is_identity(&moves!("R U R' U'").repeat(5)));
+/// assert!(is_identity(&[]));
+/// ```
+pub fn is_identity(moves: &[FaceRotation]) -> bool {
+    net_effect(moves).is_solved()
+}
+
+/// Returns how many times `moves` have to be repeated to return a cube to where it started, which
+/// is 1 for sequences without any effect.
+///
+/// ```
+/// use lib::{moves, order};
+///
+/// assert_eq!(order(&moves!("R R'")), 1);
+/// assert_eq!(order(&moves!("R")), 4);
+/// assert_eq!(order(&moves!("R2")), 2);
+/// assert_eq!(order(&moves!("R U R' U'")), 6);
+/// ```
+pub fn order(moves: &[FaceRotation]) -> usize {
+    let mut state = net_effect(moves);
+    let mut order = 1;
+    // Terminates as every element of the cube group has a finite order, at most 1260.
+    while !state.is_solved() {
+        state.apply_all(moves);
+        order += 1;
+    }
+    order
+}
+
+fn face_index(normal: [i8; 3]) -> u8 {
+    NORMALS.iter().position(|&n| n == normal).unwrap() as u8
+}
@@ -0,0 +1,16 @@
+use std::collections::HashMap;
+
+/// What the robot tells about itself when connecting, see
+/// [`crate::GanRobotController::device_info`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DeviceInfo {
+    /// The advertised name.
+    pub local_name: Option<String>,
+    /// The model number string of the device information service, if the robot has one.
+    pub model_number: Option<String>,
+    /// The advertised manufacturer data by company identifier. No known firmware sets any that
+    /// tells robots apart, so it is only there to be shown.
+    pub manufacturer_data: HashMap<u16, Vec<u8>>,
+    /// The signal strength in dBm when connecting, if the platform reports it.
+    pub rssi: Option<i16>,
+}
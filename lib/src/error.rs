@@ -0,0 +1,39 @@
+use std::fmt::Display;
+
+use crate::FaultFlags;
+
+/// Errors the caller may want to handle specifically. They are returned wrapped in
+/// [`anyhow::Error`], so use [`anyhow::Error::downcast_ref`] to match on them.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ControllerError {
+    /// The robot disconnected while waiting for moves to finish. The moves were sent, but it is
+    /// unknown how many of them were done.
+    DisconnectedDuringExecution { moves_sent: usize },
+    /// The status says there is no cube in the robot, so nothing was sent. See
+    /// [`crate::GanRobotController::with_require_cube_present`].
+    CubeNotPresent,
+    /// The controller disconnected after `timeout` without moves, so nothing was sent. See
+    /// [`crate::GanRobotController::with_inactivity_timeout`].
+    DisconnectedForInactivity { timeout: std::time::Duration },
+    /// The status reports a fault while moves were being done. See
+    /// [`crate::GanRobotController::with_fault_byte`].
+    RobotFault(FaultFlags),
+}
+
+impl Display for ControllerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DisconnectedDuringExecution { moves_sent } => write!(
+                f,
+                "GAN robot disconnected after {moves_sent} moves were sent, before they were confirmed"
+            ),
+            Self::CubeNotPresent => write!(f, "Cube not present in the GAN robot"),
+            Self::DisconnectedForInactivity { timeout } => {
+                write!(f, "Disconnected from the GAN robot after {timeout:?} without moves")
+            }
+            Self::RobotFault(faults) => write!(f, "GAN robot reported a fault: {faults:?}"),
+        }
+    }
+}
+
+impl std::error::Error for ControllerError {}
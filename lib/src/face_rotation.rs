@@ -1,55 +1,540 @@
-use std::{fmt::Display, str::FromStr};
+use std::{collections::HashMap, fmt::Display, str::FromStr};
 
-use rand::Rng;
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
 
+/// The faces the robot can turn.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Face {
+    R,
+    F,
+    D,
+    L,
+    B,
+}
+
+/// The kind of a turn. The robot does not distinguish the direction of double turns.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Turn {
+    Cw,
+    Double,
+    Ccw,
+}
+
+/// Returns the protocol value of a move, which is `face * 3 + turn` with the faces in the order of
+/// [`Face`] and the turns in the order of [`Turn`].
+///
+/// ```
+/// use lib::{byte_for, face_turn_from_byte, Face, FaceRotation, Turn};
+///
+/// assert_eq!(byte_for(Face::R, Turn::Ccw), u8::from(FaceRotation::RPrime));
+/// for b in 0..15 {
+///     let (face, turn) = face_turn_from_byte(b).unwrap();
+///     assert_eq!(byte_for(face, turn), b);
+///     assert_eq!(FaceRotation::from(b).face(), Some(face));
+///     assert_eq!(FaceRotation::from(b).turn(), Some(turn));
+/// }
+/// assert_eq!(face_turn_from_byte(15), None);
+/// ```
+pub fn byte_for(face: Face, turn: Turn) -> u8 {
+    face as u8 * 3 + turn as u8
+}
+
+/// Returns the face and turn of a protocol value, or `None` if it is not a move.
+pub fn face_turn_from_byte(b: u8) -> Option<(Face, Turn)> {
+    let face = match b / 3 {
+        0 => Face::R,
+        1 => Face::F,
+        2 => Face::D,
+        3 => Face::L,
+        4 => Face::B,
+        _ => return None,
+    };
+    let turn = match b % 3 {
+        0 => Turn::Cw,
+        1 => Turn::Double,
+        _ => Turn::Ccw,
+    };
+    Some((face, turn))
+}
+
+/// Returns the moves undoing `moves`: the inverse of each move in reverse order. Double turns
+/// are their own inverse.
+pub fn invert_sequence(moves: &[FaceRotation]) -> Vec<FaceRotation> {
+    invert_bytes(&moves.iter().map(u8::from).collect::<Vec<_>>())
+        .into_iter()
+        .map(FaceRotation::from)
+        .collect()
+}
+
+/// Returns the protocol values undoing `bytes`: the inverse of each move in reverse order.
+/// Values that are not moves are kept as they are.
+///
+/// ```
+/// use lib::{invert_bytes, CubeState, FaceRotation};
+///
+/// let scramble = [0, 4, 8, 10, 12, 7];
+/// let mut cube = CubeState::solved();
+/// for b in scramble.into_iter().chain(invert_bytes(&scramble)) {
+///     cube.apply(FaceRotation::from(b));
+/// }
+/// assert!(cube.is_solved());
+/// ```
+pub fn invert_bytes(bytes: &[u8]) -> Vec<u8> {
+    bytes
+        .iter()
+        .rev()
+        .map(|&b| match face_turn_from_byte(b) {
+            Some((face, Turn::Cw)) => byte_for(face, Turn::Ccw),
+            Some((face, Turn::Ccw)) => byte_for(face, Turn::Cw),
+            _ => b,
+        })
+        .collect()
+}
+
+/// A move the robot can do. `R2` and `R2'` are the same move, so there is only [`Self::R2`], and
+/// `R2'` parses into it. Use [`WrittenRotation`] to keep how a double turn was written.
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum FaceRotation {
     R,
     R2,
-    R2Prime,
     RPrime,
     F,
     F2,
-    F2Prime,
     FPrime,
     D,
     D2,
-    D2Prime,
     DPrime,
     L,
     L2,
-    L2Prime,
     LPrime,
     B,
     B2,
-    B2Prime,
     BPrime,
     Invalid,
 }
 
+/// Moves that swap the pieces of the U and D layers, so that a U move, which the robot cannot do,
+/// can be done as a D move in between. The sequence is its own inverse.
+pub const U_D_SWAP: [FaceRotation; 6] = {
+    use FaceRotation::*;
+    [F, B, R2, L2, BPrime, FPrime]
+};
+
+/// Rewrites moves with a turn count like `R3`, which some generators emit, into standard
+/// notation: the count is taken modulo 4, so `R3` becomes `R'`, `R3'` becomes `R`, and `R4` and
+/// `R0` are dropped. Counts above 4 are rejected as they are most likely typos. Everything else
+/// is kept as it is.
+///
+/// ```
+/// use lib::normalize_notation;
+///
+/// assert_eq!(normalize_notation("R3 F4 D2 Uw3'").unwrap(), "R' D2 Uw");
+/// assert_eq!(normalize_notation("R0 F L0'").unwrap(), "F");
+/// assert!(normalize_notation("R5").is_err());
+/// ```
+pub fn normalize_notation(s: &str) -> anyhow::Result<String> {
+    let mut tokens = vec![];
+    for token in s.split_whitespace() {
+        let (rest, prime) = match token.strip_suffix('\'') {
+            Some(rest) => (rest, true),
+            None => (token, false),
+        };
+        let base = rest.trim_end_matches(|c: char| c.is_ascii_digit());
+        let count = &rest[base.len()..];
+        if base.is_empty() || count.is_empty() || count == "2" {
+            tokens.push(token.to_string());
+            continue;
+        }
+        let count = match count.parse::<usize>() {
+            Ok(count @ 0..=4) => count,
+            _ => anyhow::bail!("Invalid move: {token}. Turn counts must be at most 4"),
+        };
+        match (if prime { 4 - count } else { count }) % 4 {
+            0 => {}
+            1 => tokens.push(base.to_string()),
+            2 => tokens.push(format!("{base}2")),
+            _ => tokens.push(format!("{base}'")),
+        }
+    }
+    Ok(tokens.join(" "))
+}
+
+/// Parses a whitespace separated move sequence like [`FaceRotation::from`] does, but translates
+/// each U move into [`U_D_SWAP`], the same move on D, and [`U_D_SWAP`] again.
+pub fn parse_moves_translating_u(s: &str) -> Vec<FaceRotation> {
+    let mut moves = vec![];
+    let push_swap = |moves: &mut Vec<FaceRotation>| {
+        // Two swaps in a row cancel each other out.
+        if moves.ends_with(&U_D_SWAP) {
+            moves.truncate(moves.len() - U_D_SWAP.len());
+        } else {
+            moves.extend_from_slice(&U_D_SWAP);
+        }
+    };
+    for token in s.split_whitespace() {
+        match token.strip_prefix(['U', 'u']) {
+            Some(suffix) => {
+                push_swap(&mut moves);
+                moves.push(FaceRotation::from(format!("d{suffix}")));
+                push_swap(&mut moves);
+            }
+            None => moves.push(FaceRotation::from(token)),
+        }
+    }
+    moves
+}
+
+impl Face {
+    /// Returns the opposite face, or `None` for [`Face::D`] since the robot cannot turn U.
+    pub fn opposite(self) -> Option<Face> {
+        match self {
+            Face::R => Some(Face::L),
+            Face::L => Some(Face::R),
+            Face::F => Some(Face::B),
+            Face::B => Some(Face::F),
+            Face::D => None,
+        }
+    }
+}
+
+/// Returns `moves` with cancellations applied: consecutive moves on the same face are merged,
+/// also across a move on the opposite face since the two commute, and turns that add up to
+/// nothing are dropped. Double turns come out as e.g. `R2`. Invalid moves are kept as they are
+/// and nothing is merged across them.
+///
+/// ```
+/// use lib::{cancel_moves, moves};
+///
+/// assert_eq!(cancel_moves(&moves!("R L R F F' D2 D2'")), moves!("R2 L"));
+/// assert_eq!(cancel_moves(&moves!("R R")), moves!("R2"));
+/// assert_eq!(cancel_moves(&moves!("R R R")), moves!("R'"));
+/// assert_eq!(cancel_moves(&moves!("R R R R")), moves!(""));
+/// assert_eq!(cancel_moves(&moves!("R R R R R")), moves!("R"));
+/// ```
+pub fn cancel_moves(moves: &[FaceRotation]) -> Vec<FaceRotation> {
+    let turns = moves.iter().map(|m| match (m.face(), m.turn()) {
+        (Some(face), Some(turn)) => Ok((face, turn as u8 + 1)),
+        _ => Err(*m),
+    });
+    cancel_turns(turns, Face::opposite)
+        .into_iter()
+        .map(|e| match e {
+            Ok((face, q)) => FaceRotation::from(byte_for(
+                face,
+                [Turn::Cw, Turn::Double, Turn::Ccw][q as usize - 1],
+            )),
+            Err(m) => m,
+        })
+        .collect()
+}
+
+/// Like [`cancel_moves`], but for a whitespace separated sequence in standard notation, which
+/// may include U moves. Anything but the face turns, such as wide moves or rotations, is kept as
+/// it is and nothing is merged across it. Turn counts like `R3` are accepted, see
+/// [`normalize_notation`].
+///
+/// ```
+/// use lib::cancel_notation;
+///
+/// assert_eq!(cancel_notation("U D U' R R Rw R").unwrap(), "D R2 Rw R");
+/// ```
+pub fn cancel_notation(s: &str) -> anyhow::Result<String> {
+    let normalized = normalize_notation(s)?;
+    let turns = normalized.split_whitespace().map(|token| {
+        let mut chars = token.chars();
+        let face = chars
+            .next()
+            .map(|c| c.to_ascii_uppercase())
+            .filter(|c| "URFDLB".contains(*c));
+        let quarters = match chars.as_str() {
+            "" => Some(1),
+            "2" | "2'" => Some(2),
+            "'" => Some(3),
+            _ => None,
+        };
+        face.zip(quarters).ok_or(token)
+    });
+    let opposite = |face| match face {
+        'U' => Some('D'),
+        'D' => Some('U'),
+        'R' => Some('L'),
+        'L' => Some('R'),
+        'F' => Some('B'),
+        'B' => Some('F'),
+        _ => None,
+    };
+    let tokens = cancel_turns(turns, opposite)
+        .into_iter()
+        .map(|e| match e {
+            Ok((face, 1)) => face.to_string(),
+            Ok((face, 2)) => format!("{face}2"),
+            Ok((face, _)) => format!("{face}'"),
+            Err(token) => token.to_string(),
+        })
+        .collect::<Vec<_>>();
+    Ok(tokens.join(" "))
+}
+
+/// Merges consecutive turns of the same face, also across one turn of the opposite face, for
+/// [`cancel_moves`] and [`cancel_notation`]. A turn is a face and its number of clockwise quarter
+/// turns, and nothing is merged across the `Err` entries.
+fn cancel_turns<F: Copy + Eq, T>(
+    turns: impl IntoIterator<Item = Result<(F, u8), T>>,
+    opposite: impl Fn(F) -> Option<F>,
+) -> Vec<Result<(F, u8), T>> {
+    let mut stack: Vec<Result<(F, u8), T>> = vec![];
+    for turn in turns {
+        let (face, quarters) = match turn {
+            Ok(turn) => turn,
+            Err(e) => {
+                stack.push(Err(e));
+                continue;
+            }
+        };
+        let n = stack.len();
+        let target = match stack.last() {
+            Some(Ok((f, _))) if *f == face => Some(n - 1),
+            Some(Ok((f, _))) if Some(*f) == opposite(face) => {
+                match n.checked_sub(2).map(|i| (i, &stack[i])) {
+                    Some((i, Ok((f, _)))) if *f == face => Some(i),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        match target {
+            Some(i) => {
+                let Ok((_, q)) = &mut stack[i] else { unreachable!() };
+                *q = (*q + quarters) % 4;
+                if *q == 0 {
+                    let _ = stack.remove(i);
+                }
+            }
+            None => stack.push(Ok((face, quarters))),
+        }
+    }
+    stack
+}
+
+/// Splits `moves` into lines of `per_line` moves, the last one possibly shorter, e.g. to print
+/// a scramble on a handout. Panics if `per_line` is zero.
+///
+/// ```
+/// use lib::{group_sequence, moves};
+///
+/// let moves = moves!("R F D L B R'");
+/// assert_eq!(group_sequence(&moves, 3), [moves!("R F D"), moves!("L B R'")]);
+/// assert_eq!(group_sequence(&moves, 4), [moves!("R F D L"), moves!("B R'")]);
+/// assert_eq!(group_sequence(&moves, 10), [moves.clone()]);
+/// assert!(group_sequence(&[], 4).is_empty());
+/// ```
+pub fn group_sequence(moves: &[FaceRotation], per_line: usize) -> Vec<Vec<FaceRotation>> {
+    moves.chunks(per_line).map(<[FaceRotation]>::to_vec).collect()
+}
+
+/// Formats `moves` as numbered lines of `per_line` moves, see [`group_sequence`].
+///
+/// ```
+/// use lib::{format_grouped, moves};
+///
+/// assert_eq!(format_grouped(&moves!("R F D L B"), 2), "1. R F\n2. D L\n3. B");
+/// assert_eq!(format_grouped(&moves!("R F"), 4), "1. R F");
+/// assert_eq!(format_grouped(&[], 4), "");
+/// ```
+pub fn format_grouped(moves: &[FaceRotation], per_line: usize) -> String {
+    group_sequence(moves, per_line)
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let line = line.iter().map(|m| m.to_string()).collect::<Vec<_>>();
+            format!("{}. {}", i + 1, line.join(" "))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Scores a scramble between 0 and 1, higher being better. The score is the fraction of moves
+/// left after [`cancel_moves`], multiplied by how evenly the remaining moves are spread over the
+/// faces. A scramble that mostly cancels out or keeps turning the same two faces scores low.
+///
+/// ```
+/// use lib::{moves, scramble_quality};
+///
+/// assert!(scramble_quality(&moves!("R R' L F F' L' B B2")) < 0.2);
+/// assert!(scramble_quality(&moves!("R F D L B R' F2 D'")) > 0.9);
+/// ```
+pub fn scramble_quality(moves: &[FaceRotation]) -> f64 {
+    let effective = cancel_moves(moves);
+    if effective.is_empty() {
+        return 0.0;
+    }
+    let mut counts = HashMap::new();
+    for face in effective.iter().filter_map(|m| m.face()) {
+        *counts.entry(face).or_insert(0usize) += 1;
+    }
+    // The entropy of the face distribution relative to the highest one possible for this many
+    // moves.
+    let max_faces = effective.len().min(5);
+    let evenness = if max_faces < 2 {
+        1.0
+    } else {
+        let total = effective.len() as f64;
+        let entropy = counts
+            .values()
+            .map(|&c| c as f64 / total)
+            .map(|p| -p * p.ln())
+            .sum::<f64>();
+        entropy / (max_faces as f64).ln()
+    };
+    effective.len() as f64 / moves.len() as f64 * evenness
+}
+
+/// Builds a `Vec<FaceRotation>`, either from a string in standard notation which is validated at
+/// compile time and may contain U moves (see [`parse_moves_translating_u`]), or from variant
+/// names.
+///
+/// ```
+/// use lib::{moves, FaceRotation};
+///
+/// assert_eq!(moves!("R F2 D'"), vec![FaceRotation::R, FaceRotation::F2, FaceRotation::DPrime]);
+/// assert_eq!(moves![R, F2, DPrime], moves!("R F2 D'"));
+/// assert_eq!(moves!("U").len(), 13);
+/// ```
+///
+/// Invalid moves fail to compile:
+///
+/// ```compile_fail
+/// let moves = lib::moves!("R X");
+/// ```
+#[macro_export]
+macro_rules! moves {
+    ($s:literal) => {{
+        const _: () = $crate::assert_valid_moves($s);
+        $crate::parse_moves_translating_u($s)
+    }};
+    ($($m:ident),* $(,)?) => {
+        vec![$($crate::FaceRotation::$m),*]
+    };
+}
+
+/// Panics if `s` contains anything but moves in standard notation, so that [`moves!`] can reject
+/// them at compile time.
+#[doc(hidden)]
+pub const fn assert_valid_moves(s: &str) {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i].is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        assert!(
+            matches!(bytes[i].to_ascii_uppercase(), b'R' | b'F' | b'D' | b'L' | b'B' | b'U'),
+            "Invalid move"
+        );
+        i += 1;
+        if i < bytes.len() && bytes[i] == b'2' {
+            i += 1;
+        }
+        if i < bytes.len() && bytes[i] == b'\'' {
+            i += 1;
+        }
+        assert!(i == bytes.len() || bytes[i].is_ascii_whitespace(), "Invalid move");
+    }
+}
+
+impl FaceRotation {
+    /// Returns the rotation turning `face` by `quarter_turns` clockwise quarter turns, taken
+    /// modulo 4 so that negative counts turn counterclockwise, or `None` if they add up to no
+    /// turn, e.g. for moves from a solver that gives a face and a count.
+    ///
+    /// ```
+    /// use lib::{Face, FaceRotation};
+    ///
+    /// assert_eq!(FaceRotation::from_quarter_turns(Face::R, 1), Some(FaceRotation::R));
+    /// assert_eq!(FaceRotation::from_quarter_turns(Face::R, -1), Some(FaceRotation::RPrime));
+    /// assert_eq!(FaceRotation::from_quarter_turns(Face::F, 2), Some(FaceRotation::F2));
+    /// assert_eq!(FaceRotation::from_quarter_turns(Face::F, -2), Some(FaceRotation::F2));
+    /// assert_eq!(FaceRotation::from_quarter_turns(Face::D, 3), Some(FaceRotation::DPrime));
+    /// assert_eq!(FaceRotation::from_quarter_turns(Face::B, 5), Some(FaceRotation::B));
+    /// assert_eq!(FaceRotation::from_quarter_turns(Face::L, -4), None);
+    /// assert_eq!(FaceRotation::from_quarter_turns(Face::L, 0), None);
+    /// ```
+    pub fn from_quarter_turns(face: Face, quarter_turns: i8) -> Option<FaceRotation> {
+        let turn = match quarter_turns.rem_euclid(4) {
+            0 => return None,
+            1 => Turn::Cw,
+            2 => Turn::Double,
+            _ => Turn::Ccw,
+        };
+        Some(FaceRotation::from(byte_for(face, turn)))
+    }
+
+    /// Returns the face turned by this rotation, or `None` if it is invalid.
+    pub fn face(self) -> Option<Face> {
+        face_turn_from_byte(u8::from(self)).map(|(face, _)| face)
+    }
+
+    /// Returns the kind of turn of this rotation, or `None` if it is invalid.
+    pub fn turn(self) -> Option<Turn> {
+        face_turn_from_byte(u8::from(self)).map(|(_, turn)| turn)
+    }
+
+    /// Returns the same kind of rotation on another face, e.g. `F'` for `R'` and [`Face::F`].
+    ///
+    /// ```
+    /// use lib::{Face, FaceRotation};
+    ///
+    /// assert_eq!(FaceRotation::RPrime.with_face(Face::F), FaceRotation::FPrime);
+    /// assert_eq!(FaceRotation::D2.with_face(Face::B), FaceRotation::B2);
+    /// assert_eq!(FaceRotation::Invalid.with_face(Face::R), FaceRotation::Invalid);
+    /// ```
+    pub fn with_face(self, face: Face) -> FaceRotation {
+        match self.turn() {
+            Some(turn) => FaceRotation::from(byte_for(face, turn)),
+            None => self,
+        }
+    }
+
+    /// Returns `true` if both rotations turn the same face.
+    pub fn same_face(self, other: FaceRotation) -> bool {
+        self.face().is_some() && self.face() == other.face()
+    }
+
+    /// Returns `true` if both rotations have the same physical effect. Since `R2'` parses into
+    /// `R2`, this is the same as comparing them.
+    ///
+    /// ```
+    /// use lib::FaceRotation;
+    ///
+    /// assert!(FaceRotation::R2.same_effect(FaceRotation::from("R2'")));
+    /// assert!(FaceRotation::FPrime.same_effect(FaceRotation::FPrime));
+    /// assert!(!FaceRotation::R.same_effect(FaceRotation::RPrime));
+    /// assert!(!FaceRotation::R2.same_effect(FaceRotation::F2));
+    /// ```
+    pub fn same_effect(self, other: FaceRotation) -> bool {
+        self == other
+    }
+}
+
 impl From<FaceRotation> for u8 {
     fn from(r: FaceRotation) -> u8 {
         use FaceRotation::*;
         match r {
             R => 0,
             R2 => 1,
-            R2Prime => 1,
             RPrime => 2,
             F => 3,
             F2 => 4,
-            F2Prime => 4,
             FPrime => 5,
             D => 6,
             D2 => 7,
-            D2Prime => 7,
             DPrime => 8,
             L => 9,
             L2 => 10,
-            L2Prime => 10,
             LPrime => 11,
             B => 12,
             B2 => 13,
-            B2Prime => 13,
             BPrime => 14,
             Invalid => 255,
         }
@@ -62,28 +547,52 @@ impl From<&FaceRotation> for u8 {
     }
 }
 
+impl From<u8> for FaceRotation {
+    fn from(m: u8) -> Self {
+        use FaceRotation::*;
+        match m {
+            0 => R,
+            1 => R2,
+            2 => RPrime,
+            3 => F,
+            4 => F2,
+            5 => FPrime,
+            6 => D,
+            7 => D2,
+            8 => DPrime,
+            9 => L,
+            10 => L2,
+            11 => LPrime,
+            12 => B,
+            13 => B2,
+            14 => BPrime,
+            _ => Invalid,
+        }
+    }
+}
+
 impl From<String> for FaceRotation {
     fn from(s: String) -> Self {
         match s.to_lowercase().as_str() {
             "r" => FaceRotation::R,
             "r2" => FaceRotation::R2,
-            "r2'" => FaceRotation::R2Prime,
+            "r2'" => FaceRotation::R2,
             "r'" => FaceRotation::RPrime,
             "f" => FaceRotation::F,
             "f2" => FaceRotation::F2,
-            "f2'" => FaceRotation::F2Prime,
+            "f2'" => FaceRotation::F2,
             "f'" => FaceRotation::FPrime,
             "d" => FaceRotation::D,
             "d2" => FaceRotation::D2,
-            "d2'" => FaceRotation::D2Prime,
+            "d2'" => FaceRotation::D2,
             "d'" => FaceRotation::DPrime,
             "l" => FaceRotation::L,
             "l2" => FaceRotation::L2,
-            "l2'" => FaceRotation::L2Prime,
+            "l2'" => FaceRotation::L2,
             "l'" => FaceRotation::LPrime,
             "b" => FaceRotation::B,
             "b2" => FaceRotation::B2,
-            "b2'" => FaceRotation::B2Prime,
+            "b2'" => FaceRotation::B2,
             "b'" => FaceRotation::BPrime,
             _ => FaceRotation::Invalid,
         }
@@ -104,23 +613,23 @@ impl FromStr for FaceRotation {
         match s {
             "R" => Ok(R),
             "R2" => Ok(R2),
-            "R2'" => Ok(R2Prime),
+            "R2'" => Ok(R2),
             "R'" => Ok(RPrime),
             "F" => Ok(F),
             "F2" => Ok(F2),
-            "F2'" => Ok(F2Prime),
+            "F2'" => Ok(F2),
             "F'" => Ok(FPrime),
             "D" => Ok(D),
             "D2" => Ok(D2),
-            "D2'" => Ok(D2Prime),
+            "D2'" => Ok(D2),
             "D'" => Ok(DPrime),
             "L" => Ok(L),
             "L2" => Ok(L2),
-            "L2'" => Ok(L2Prime),
+            "L2'" => Ok(L2),
             "L'" => Ok(LPrime),
             "B" => Ok(B),
             "B2" => Ok(B2),
-            "B2'" => Ok(B2Prime),
+            "B2'" => Ok(B2),
             "B'" => Ok(BPrime),
             _ => Ok(Invalid),
         }
@@ -133,23 +642,18 @@ impl Display for FaceRotation {
         let s = match self {
             R => "R",
             R2 => "R2",
-            R2Prime => "R2'",
             RPrime => "R'",
             F => "F",
             F2 => "F2",
-            F2Prime => "F2'",
             FPrime => "F'",
             D => "D",
             D2 => "D2",
-            D2Prime => "D2'",
             DPrime => "D'",
             L => "L",
             L2 => "L2",
-            L2Prime => "L2'",
             LPrime => "L'",
             B => "B",
             B2 => "B2",
-            B2Prime => "B2'",
             BPrime => "B'",
             Invalid => "(Invalid)",
         };
@@ -157,6 +661,78 @@ impl Display for FaceRotation {
     }
 }
 
+/// A [`FaceRotation`] annotated with how it was written, to echo moves back as they were given:
+/// `primed` is set for a double turn written like `R2'`.
+///
+/// ```
+/// use lib::{FaceRotation, WrittenRotation};
+///
+/// let written = WrittenRotation::from("R2'");
+/// assert_eq!(written.rotation, FaceRotation::R2);
+/// assert_eq!(written.to_string(), "R2'");
+/// assert_eq!(WrittenRotation::from(FaceRotation::R2).to_string(), "R2");
+/// assert!(!WrittenRotation::from("R'").primed);
+/// ```
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct WrittenRotation {
+    pub rotation: FaceRotation,
+    pub primed: bool,
+}
+
+impl From<FaceRotation> for WrittenRotation {
+    fn from(rotation: FaceRotation) -> Self {
+        Self { rotation, primed: false }
+    }
+}
+
+impl From<WrittenRotation> for FaceRotation {
+    fn from(written: WrittenRotation) -> Self {
+        written.rotation
+    }
+}
+
+impl From<&str> for WrittenRotation {
+    fn from(s: &str) -> Self {
+        let rotation = FaceRotation::from(s);
+        let primed = rotation.turn() == Some(Turn::Double) && s.ends_with('\'');
+        Self { rotation, primed }
+    }
+}
+
+impl Display for WrittenRotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.rotation, if self.primed { "'" } else { "" })
+    }
+}
+
+/// Returns `moves` as [`WrittenRotation`]s for display. With `notation_variety`, each double
+/// turn is written as e.g. `R2` or `R2'` at random, as official scramblers do, which the robot
+/// does the same way. Without it, double turns are written as `R2`.
+///
+/// ```
+/// use lib::{moves, to_written};
+///
+/// let written = to_written(&moves!("R2").repeat(100), true);
+/// assert!(written.iter().any(|w| w.to_string() == "R2"));
+/// assert!(written.iter().any(|w| w.to_string() == "R2'"));
+/// assert!(written.iter().all(|w| u8::from(w.rotation) == 1));
+/// assert!(to_written(&moves!("R2 F'"), true)[1].to_string() == "F'");
+/// assert!(to_written(&moves!("R2").repeat(100), false).iter().all(|w| !w.primed));
+/// ```
+pub fn to_written(moves: &[FaceRotation], notation_variety: bool) -> Vec<WrittenRotation> {
+    let mut rng = rand::thread_rng();
+    moves
+        .iter()
+        .map(|&rotation| WrittenRotation {
+            rotation,
+            primed: notation_variety && rotation.turn() == Some(Turn::Double) && rng.gen(),
+        })
+        .collect()
+}
+
+/// The set of face rotations the robot can perform, in a fixed order: `R`, `R2`, `R'`, then the
+/// same for `F`, `D`, `L` and `B`, which is the order of the protocol values. This order is part
+/// of the API, so an index always refers to the same rotation.
 pub struct FaceRotationMap {
     map: Vec<FaceRotation>,
 }
@@ -170,17 +746,161 @@ impl Default for FaceRotationMap {
 impl FaceRotationMap {
     pub fn new() -> Self {
         use FaceRotation::*;
-        let map = vec![
-            R, R2, R2Prime, RPrime, F, F2, F2Prime, FPrime, D, D2, D2Prime, DPrime, L, L2, L2Prime,
-            LPrime, B, B2, B2Prime, BPrime,
-        ];
+        let map = vec![R, R2, RPrime, F, F2, FPrime, D, D2, DPrime, L, L2, LPrime, B, B2, BPrime];
         Self { map }
     }
 
+    /// Returns `n` moves, each picked independently and uniformly from the map. The sequence is
+    /// not reproducible; use [`Self::get_moves_in_order`] when an exact sequence is needed.
     pub fn get_random_moves(&self, n: usize) -> Vec<FaceRotation> {
         (0..n)
             .map(|_| rand::thread_rng().gen_range(0..self.map.len()))
             .map(|i| self.map[i])
             .collect::<Vec<FaceRotation>>()
     }
+
+    /// Returns a random move on a different face than `previous`, so that consecutive moves never
+    /// turn the same face.
+    ///
+    /// ```
+    /// use lib::FaceRotationMap;
+    ///
+    /// let map = FaceRotationMap::new();
+    /// let mut previous = None;
+    /// for _ in 0..1000 {
+    ///     let m = map.get_random_move_avoiding(previous);
+    ///     assert!(previous.is_none_or(|p| !p.same_face(m)));
+    ///     previous = Some(m);
+    /// }
+    /// ```
+    pub fn get_random_move_avoiding(&self, previous: Option<FaceRotation>) -> FaceRotation {
+        let candidates = self
+            .map
+            .iter()
+            .filter(|m| previous.is_none_or(|p| !p.same_face(**m)))
+            .collect::<Vec<_>>();
+        *candidates[rand::thread_rng().gen_range(0..candidates.len())]
+    }
+
+    /// Returns `n` random moves with each face picked according to `weights`. Faces missing from
+    /// `weights` get a weight of 1, so an empty map is uniform. Weights must not be negative and
+    /// at least one face needs a positive weight.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    ///
+    /// use lib::{Face, FaceRotationMap};
+    ///
+    /// let map = FaceRotationMap::new();
+    /// let weights = HashMap::from([(Face::B, 0.0), (Face::R, 3.0)]);
+    /// let scramble = map.get_weighted_scramble(1000, &weights).unwrap();
+    /// assert!(scramble.iter().all(|m| m.face() != Some(Face::B)));
+    /// assert!(map.get_weighted_scramble(1, &HashMap::from([(Face::R, -1.0)])).is_err());
+    /// ```
+    pub fn get_weighted_scramble(
+        &self,
+        n: usize,
+        weights: &HashMap<Face, f64>,
+    ) -> anyhow::Result<Vec<FaceRotation>> {
+        if let Some((face, weight)) = weights.iter().find(|(_, w)| !w.is_finite() || **w < 0.0) {
+            anyhow::bail!("Invalid weight for {face:?}: {weight}. Must be a non-negative number");
+        }
+        let weights = self
+            .map
+            .iter()
+            .map(|m| m.face().and_then(|f| weights.get(&f).copied()).unwrap_or(1.0))
+            .collect::<Vec<_>>();
+        let distribution = WeightedIndex::new(&weights)
+            .map_err(|_| anyhow::anyhow!("At least one face must have a positive weight"))?;
+        let mut rng = rand::thread_rng();
+        Ok((0..n).map(|_| self.map[distribution.sample(&mut rng)]).collect())
+    }
+
+    /// Returns `n` random moves whose [`scramble_quality`] is at least `threshold`, regenerating
+    /// the scramble until one clears it. Fails if none does after a thousand attempts.
+    pub fn generate_scramble_min_quality(
+        &self,
+        n: usize,
+        threshold: f64,
+    ) -> anyhow::Result<Vec<FaceRotation>> {
+        const MAX_ATTEMPTS: usize = 1000;
+        if !(0.0..=1.0).contains(&threshold) {
+            anyhow::bail!("Invalid quality threshold: {threshold}. Must be between 0 and 1");
+        }
+        for _ in 0..MAX_ATTEMPTS {
+            let moves = self.get_random_moves(n);
+            if scramble_quality(&moves) >= threshold {
+                return Ok(moves);
+            }
+        }
+        anyhow::bail!(
+            "No scramble of {n} moves reached a quality of {threshold} in {MAX_ATTEMPTS} attempts"
+        )
+    }
+
+    /// Returns a random scramble of `effective` moves that does not shorten under
+    /// [`cancel_moves`], built by adding random moves until the cancelled sequence is that long.
+    /// This is a heuristic: a scramble without cancellations can still be solvable in fewer
+    /// moves, so it does not guarantee a minimum optimal solution length.
+    ///
+    /// ```
+    /// use lib::{cancel_moves, FaceRotationMap};
+    ///
+    /// let map = FaceRotationMap::new();
+    /// for _ in 0..100 {
+    ///     let scramble = map.generate_scramble_min_length(20);
+    ///     assert_eq!(cancel_moves(&scramble), scramble);
+    ///     assert_eq!(scramble.len(), 20);
+    /// }
+    /// ```
+    pub fn generate_scramble_min_length(&self, effective: usize) -> Vec<FaceRotation> {
+        let mut moves = vec![];
+        while moves.len() < effective {
+            moves.push(self.get_random_move_avoiding(moves.last().copied()));
+            // Adding a move shortens or lengthens the cancelled sequence by at most one.
+            moves = cancel_moves(&moves);
+        }
+        moves
+    }
+
+    /// Returns `n` random moves together with the moves solving them, for doing one and then the
+    /// other, e.g. to demonstrate solving.
+    ///
+    /// ```
+    /// use lib::{CubeState, FaceRotationMap};
+    ///
+    /// let (scramble, solution) = FaceRotationMap::new().scramble_with_solution(20);
+    /// let mut cube = CubeState::solved();
+    /// cube.apply_all(&scramble);
+    /// cube.apply_all(&solution);
+    /// assert!(cube.is_solved());
+    /// ```
+    pub fn scramble_with_solution(&self, n: usize) -> (Vec<FaceRotation>, Vec<FaceRotation>) {
+        let scramble = self.get_random_moves(n);
+        let solution = invert_sequence(&scramble);
+        (scramble, solution)
+    }
+
+    /// Returns the moves at the given indices of the map, in the given order.
+    ///
+    /// ```
+    /// use lib::{moves, FaceRotation, FaceRotationMap};
+    ///
+    /// let map = FaceRotationMap::new();
+    /// assert_eq!(map.get_moves_in_order(&[0, 4, 8, 14]).unwrap(), moves!("R F2 D' B'"));
+    /// let all = map.get_moves_in_order(&(0..15).collect::<Vec<_>>()).unwrap();
+    /// assert_eq!(all, (0..15).map(FaceRotation::from).collect::<Vec<_>>());
+    /// let e = map.get_moves_in_order(&[15]).unwrap_err();
+    /// assert_eq!(e.to_string(), "Invalid index: 15. Must be less than 15");
+    /// ```
+    pub fn get_moves_in_order(&self, indices: &[usize]) -> anyhow::Result<Vec<FaceRotation>> {
+        indices
+            .iter()
+            .map(|&i| {
+                self.map.get(i).copied().ok_or_else(|| {
+                    anyhow::anyhow!("Invalid index: {i}. Must be less than {}", self.map.len())
+                })
+            })
+            .collect()
+    }
 }
@@ -0,0 +1,36 @@
+bitflags::bitflags! {
+    /// Faults reported in a byte of the status payload, see
+    /// [`crate::GanRobotController::with_fault_byte`]. The layout is assumed, as no known firmware
+    /// documents one, so unnamed bits are kept too and every set bit counts as a fault.
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+    pub struct FaultFlags: u8 {
+        /// A motor could not finish a turn, e.g. because the cube is jammed.
+        const STALLED = 0x01;
+        const OVERHEATED = 0x02;
+        const LOW_BATTERY = 0x04;
+        const _ = !0;
+    }
+}
+
+impl FaultFlags {
+    /// Decodes the faults from the byte at `index` of a status payload, or none if the payload
+    /// is shorter.
+    ///
+    /// ```
+    /// use lib::FaultFlags;
+    ///
+    /// assert_eq!(FaultFlags::from_status(&[3, 0x00], 1), FaultFlags::empty());
+    /// assert_eq!(FaultFlags::from_status(&[3, 0x01], 1), FaultFlags::STALLED);
+    /// assert_eq!(
+    ///     FaultFlags::from_status(&[3, 0x06], 1),
+    ///     FaultFlags::OVERHEATED | FaultFlags::LOW_BATTERY
+    /// );
+    /// assert_eq!(FaultFlags::from_status(&[3, 0x80], 1).bits(), 0x80);
+    /// assert_eq!(FaultFlags::from_status(&[3], 1), FaultFlags::empty());
+    /// ```
+    pub fn from_status(status: &[u8], index: usize) -> Self {
+        status
+            .get(index)
+            .map_or(Self::empty(), |&b| Self::from_bits_retain(b))
+    }
+}
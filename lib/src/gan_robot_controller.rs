@@ -1,40 +1,369 @@
-use std::ops::Deref;
+use std::{
+    collections::HashMap,
+    fs,
+    future::Future,
+    ops::Deref,
+    path::Path,
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
+use crate::logging::{hex, info, trace, warn};
 use btleplug::{
     api::{
-        Central, CentralEvent, Characteristic, Manager as _, Peripheral as _, ScanFilter, WriteType,
+        self, Central, CentralEvent, CharPropFlags, Characteristic, Manager as _, Peripheral as _,
+        ScanFilter, WriteType,
     },
     platform::{Adapter, Manager, Peripheral, PeripheralId},
 };
-use futures::StreamExt;
-use log::info;
-use tokio::time::{sleep, Duration};
+use futures::{future::Either, Stream, StreamExt};
+use tokio::{
+    sync::{mpsc::Receiver, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
 use uuid::Uuid;
 
-use crate::{FaceRotation, FaceRotationMap, MAX_MOVES_PER_WRITE};
+use crate::{
+    cancel_moves, face_turn_from_byte, invert_bytes, invert_sequence, net_effect,
+    normalize_notation,
+    pack::{pack_frame, FRAME_LEN},
+    parse_moves_translating_u, pattern, pattern_state,
+    tnoodle::strip_numbering,
+    watchdog::{Activity, Watchdog},
+    ControllerError, CubeState, DeviceInfo, Face, FaceRotation, FaceRotationMap, FaultFlags,
+    Orientation, PauseHandle, ScrambleSource, Sleeper, Subscription, TokioSleeper,
+    MAX_MOVES_PER_WRITE, PATTERNS, U_D_SWAP,
+};
+
+/// The service the move and status characteristics of the GAN robot belong to.
+pub const GAN_ROBOT_SERVICE: &str = "0000fff0-0000-1000-8000-00805f9b34fb";
 
 const QUANTUM_TURN_DURATION_MS: usize = 150;
 const DOUBLE_TURN_DURATION_MS: usize = 250;
+/// Below this signal strength, connections tend to drop.
+const LOW_RSSI_DBM: i16 = -85;
+const DROPPED_MOVE_TOLERANCE: usize = 1;
+const POLL_INTERVAL_MS: u64 = 100;
+const STUCK_TIMEOUT_MS: u64 = 5000;
+const STATUS_READ_DELAY_MS: u64 = 30;
+/// What the bytes of a frame after the moves are set to by default.
+const FILL_BYTE: u8 = 0xff;
+/// The model number string of the standard device information service.
+const MODEL_NUMBER_CHARACTERISTIC: &str = "00002a24-0000-1000-8000-00805f9b34fb";
+
+/// What to do when the remaining count read right after a write shows that the robot got fewer
+/// moves than were sent.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum DroppedMoveCheck {
+    Off,
+    #[default]
+    Warn,
+    Error,
+}
+
+impl FromStr for DroppedMoveCheck {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "warn" => Ok(Self::Warn),
+            "error" => Ok(Self::Error),
+            _ => anyhow::bail!("Invalid dropped move check: {s}. Must be off, warn or error"),
+        }
+    }
+}
+
+/// A decoded read of the status characteristic, see [`GanRobotController::status`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RobotStatus {
+    pub remaining_moves: u8,
+    /// Whether a cube is in the robot, or `None` if the firmware does not say. No known firmware
+    /// reports it, so this is only decoded where
+    /// [`GanRobotController::with_cube_presence_flag`] says where to find it.
+    pub cube_present: Option<bool>,
+    /// The faults the robot reports, empty unless [`GanRobotController::with_fault_byte`] says
+    /// where to find them.
+    pub faults: FaultFlags,
+}
+
+/// What [`GanRobotController::scramble`] did.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ScrambleReport {
+    pub moves: Vec<FaceRotation>,
+    /// How long the moves were estimated to take with the [`TimingConfig`] of the controller.
+    pub estimated: Duration,
+    /// How long the moves took, from the first write until the robot was done, if enabled with
+    /// [`GanRobotController::with_measure_duration`].
+    pub actual: Option<Duration>,
+}
+
+/// How sequences are split into writes, on top of the maximum moves per write. Between writes,
+/// the controller waits for the robot to finish, pauses if asked to and calls the chunk hooks.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BatchBy {
+    /// At most this many moves per write.
+    MoveCount(usize),
+    /// At most this much estimated motion per write, see [`TimingConfig`]. A single move
+    /// taking longer is still sent on its own.
+    Duration(Duration),
+}
+
+impl Default for BatchBy {
+    fn default() -> Self {
+        Self::MoveCount(MAX_MOVES_PER_WRITE)
+    }
+}
+
+/// When [`GanRobotController::do_moves_raw`] returns after writing its moves.
+///
+#[cfg_attr(feature = "test-util", doc = "```")]
+#[cfg_attr(not(feature = "test-util"), doc = "```ignore")]
+/// use std::time::Duration;
+///
+/// use lib::{CompletionPredicate, DroppedMoveCheck, FakePeripheral, GanRobotController};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> anyhow::Result<()> {
+/// let move_uuid = "0000fff3-0000-1000-8000-00805f9b34fb";
+/// let status_uuid = "0000fff2-0000-1000-8000-00805f9b34fb";
+/// let robot = FakePeripheral::new(move_uuid, status_uuid)?;
+/// let status = robot.status_characteristic();
+/// robot.push_read(status, vec![0]);
+/// let controller = GanRobotController::try_new("GAN", move_uuid, status_uuid)?
+///     .with_dropped_move_check(DroppedMoveCheck::Off)
+///     .from_peripheral(robot.clone())
+///     .await?;
+///
+/// // Waits for the last move.
+/// for remaining in [0, 3, 2, 1, 0] {
+///     robot.push_read(status, vec![remaining]);
+/// }
+/// controller.do_moves_raw(&[0, 3, 6], CompletionPredicate::Drained).await?;
+///
+/// // Returns while 2 moves are left, so the next read gets the count after that.
+/// for remaining in [0, 3, 2, 1] {
+///     robot.push_read(status, vec![remaining]);
+/// }
+/// controller.do_moves_raw(&[0, 3, 6], CompletionPredicate::BelowThreshold(3)).await?;
+/// assert_eq!(controller.status().await?.remaining_moves, 1);
+///
+/// // Gives up on a robot that never finishes instead of failing as stuck.
+/// for remaining in [0, 3] {
+///     robot.push_read(status, vec![remaining]);
+/// }
+/// let completion = CompletionPredicate::Timeout(Duration::from_millis(200));
+/// controller.do_moves_raw(&[0, 3, 6], completion).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum CompletionPredicate {
+    /// Once the robot reports no remaining moves.
+    #[default]
+    Drained,
+    /// Once fewer than this many moves remain, so that the next write can overlap with the last
+    /// moves. `BelowThreshold(0)` and `BelowThreshold(1)` are the same as `Drained`. Without a
+    /// status characteristic, this waits until all moves are estimated to be done.
+    BelowThreshold(u8),
+    /// Once drained, or after this long, whichever comes first. Running out of time is not an
+    /// error, but a robot found stuck before is.
+    Timeout(Duration),
+}
+
+/// How long the robot takes per move, which decides when to start polling for a write to be
+/// done and how [`BatchBy::Duration`] splits sequences. Faces are those of the robot, i.e. after
+/// orientation remapping.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TimingConfig {
+    /// The duration of a quarter turn, 150 ms by default.
+    pub quarter_turn: Duration,
+    /// The duration of a double turn, 250 ms by default.
+    pub double_turn: Duration,
+    /// The durations of a quarter and a double turn for faces deviating from the above, e.g.
+    /// because of a slower motor.
+    pub face_overrides: HashMap<Face, (Duration, Duration)>,
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        Self {
+            quarter_turn: Duration::from_millis(QUANTUM_TURN_DURATION_MS as u64),
+            double_turn: Duration::from_millis(DOUBLE_TURN_DURATION_MS as u64),
+            face_overrides: HashMap::new(),
+        }
+    }
+}
+
+impl TimingConfig {
+    /// Estimates how long the robot takes for the given moves, not counting BLE latency.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use lib::{moves, Face, TimingConfig};
+    ///
+    /// let mut timing = TimingConfig::default();
+    /// let moves = moves!("R B B2");
+    /// assert_eq!(timing.estimated_duration(&moves), Duration::from_millis(550));
+    /// let slow_b = (Duration::from_millis(200), Duration::from_millis(300));
+    /// timing.face_overrides.insert(Face::B, slow_b);
+    /// assert_eq!(timing.estimated_duration(&moves), Duration::from_millis(650));
+    /// ```
+    pub fn estimated_duration(&self, moves: &[FaceRotation]) -> Duration {
+        let ms = moves
+            .iter()
+            .filter(|m| **m != FaceRotation::Invalid)
+            .map(|m| self.move_duration(u8::from(m)))
+            .sum::<usize>();
+        Duration::from_millis(ms as u64)
+    }
+
+    /// Returns the duration of the move with protocol value `m` in milliseconds.
+    fn move_duration(&self, m: u8) -> usize {
+        let (quarter_turn, double_turn) = face_turn_from_byte(m)
+            .and_then(|(face, _)| self.face_overrides.get(&face).copied())
+            .unwrap_or((self.quarter_turn, self.double_turn));
+        let duration = if is_double_turn_move(m) { double_turn } else { quarter_turn };
+        duration.as_millis() as usize
+    }
+}
+
+/// A service of the robot, see [`GanRobotController::describe_gatt`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ServiceInfo {
+    pub uuid: Uuid,
+    pub characteristics: Vec<CharacteristicInfo>,
+}
+
+/// A characteristic of a [`ServiceInfo`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CharacteristicInfo {
+    pub uuid: Uuid,
+    pub properties: CharPropFlags,
+}
+
+/// What to do when writing a chunk of a sequence fails with a BLE error, see
+/// [`GanRobotController::with_error_policy`].
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum ErrorPolicy {
+    /// Fail the whole sequence.
+    #[default]
+    Abort,
+    /// Retry the chunk up to this many times, then fail the sequence.
+    RetryThenAbort(usize),
+    /// Retry the chunk up to this many times, then leave its moves out and go on with the next
+    /// chunk. The cube then ends up in another state than intended.
+    RetryThenSkip(usize),
+}
+
+/// A hook called with each move when it is sent, see
+/// [`GanRobotController::with_on_move_sent`].
+pub type OnMoveSent = dyn Fn(FaceRotation) + Send + Sync;
+
+/// A hook called with the moves of a chunk, see [`GanRobotController::with_before_chunk`].
+pub type OnChunk = dyn Fn(&[FaceRotation]) + Send + Sync;
 
 pub trait State {}
 
+/// The state of a controller that is configured but not connected yet. It is cheap to clone, e.g.
+/// to connect to several robots with the same settings or to retry a failed connection.
+#[derive(Clone)]
 pub struct Uninitialized {
     name: String,
+    exact_name_case: bool,
+    low_rssi_threshold: i16,
     move_characteristic: Uuid,
     status_characteristic: Uuid,
+    capacity_characteristic: Option<Uuid>,
+    cube_state_characteristic: Option<Uuid>,
+    service: Option<Uuid>,
+    max_sequence_len: Option<usize>,
+    max_moves_per_write: usize,
+    batch_by: BatchBy,
+    orientation: Orientation,
+    write_retries: usize,
+    write_type: WriteType,
+    status_read_delay: Duration,
+    error_policy: ErrorPolicy,
+    settle_at_end: bool,
+    timing: TimingConfig,
+    merge_moves: bool,
+    cube_presence_flag: Option<(usize, u8)>,
+    fault_byte: Option<usize>,
+    require_cube_present: bool,
+    dropped_move_check: DroppedMoveCheck,
+    inactivity_timeout: Option<Duration>,
+    reuse_discovery: bool,
+    measure_duration: bool,
+    fill_byte: u8,
+    wait_for_idle_on_connect: bool,
+    sleeper: Arc<dyn Sleeper>,
+    on_move_sent: Option<Arc<OnMoveSent>>,
+    before_chunk: Option<Arc<OnChunk>>,
+    after_chunk: Option<Arc<OnChunk>>,
+    pause_handle: PauseHandle,
 }
 
 impl State for Uninitialized {}
 
-pub struct Connected {
-    gan_robot: Peripheral,
+/// The state of a connected controller. `P` is the peripheral of the robot, which is only
+/// replaced in tests, see [`GanRobotController::from_peripheral`].
+pub struct Connected<P = Peripheral> {
+    gan_robot: P,
+    device_info: DeviceInfo,
+    low_rssi_threshold: i16,
     move_characteristic: Characteristic,
-    status_characteristic: Characteristic,
+    /// `None` if the robot does not have it, in which case moves are timed open loop.
+    status_characteristic: Option<Characteristic>,
+    capacity_characteristic: Option<Characteristic>,
+    cube_state_characteristic: Option<Characteristic>,
     face_rotation_map: FaceRotationMap,
+    max_sequence_len: Option<usize>,
+    max_moves_per_write: usize,
+    batch_by: BatchBy,
+    orientation: Orientation,
+    write_retries: usize,
+    write_type: WriteType,
+    status_read_delay: Duration,
+    error_policy: ErrorPolicy,
+    settle_at_end: bool,
+    timing: TimingConfig,
+    merge_moves: bool,
+    cube_presence_flag: Option<(usize, u8)>,
+    fault_byte: Option<usize>,
+    require_cube_present: bool,
+    dropped_move_check: DroppedMoveCheck,
+    measure_duration: bool,
+    fill_byte: u8,
+    sleeper: Arc<dyn Sleeper>,
+    on_move_sent: Option<Arc<OnMoveSent>>,
+    before_chunk: Option<Arc<OnChunk>>,
+    after_chunk: Option<Arc<OnChunk>>,
+    pause_handle: PauseHandle,
+    u_d_swapped: AtomicBool,
+    /// The protocol values of all moves written so far, see [`GanRobotController::history`].
+    history: std::sync::Mutex<Vec<u8>>,
+    /// When the moves written so far are estimated to be done, for timing them without status.
+    busy_until: std::sync::Mutex<Instant>,
+    /// Held while moves are written and done, so that concurrent calls do not interleave.
+    executing: Mutex<()>,
+    watchdog: Option<Watchdog>,
 }
 
-impl State for Connected {}
+/// Held while moves are written and done, see [`GanRobotController::execute`].
+struct Executing<'a> {
+    _lock: MutexGuard<'a, ()>,
+    _activity: Option<Activity>,
+}
+
+impl<P: api::Peripheral> State for Connected<P> {}
 
+#[derive(Clone)]
 pub struct GanRobotController<S>
 where
     S: State,
@@ -63,38 +392,398 @@ impl GanRobotController<Uninitialized> {
         let move_characteristic = Uuid::parse_str(move_characteristic)?;
         let status_characteristic = Uuid::parse_str(status_characteristic)?;
         Ok(Self {
-            state: Uninitialized { name, move_characteristic, status_characteristic },
+            state: Uninitialized {
+                name,
+                exact_name_case: false,
+                low_rssi_threshold: LOW_RSSI_DBM,
+                move_characteristic,
+                status_characteristic,
+                capacity_characteristic: None,
+                cube_state_characteristic: None,
+                service: Some(Uuid::parse_str(GAN_ROBOT_SERVICE)?),
+                max_sequence_len: None,
+                max_moves_per_write: MAX_MOVES_PER_WRITE,
+                batch_by: BatchBy::default(),
+                orientation: Orientation::default(),
+                write_retries: 0,
+                write_type: WriteType::WithoutResponse,
+                status_read_delay: Duration::from_millis(STATUS_READ_DELAY_MS),
+                error_policy: ErrorPolicy::default(),
+                settle_at_end: false,
+                timing: TimingConfig::default(),
+                merge_moves: false,
+                cube_presence_flag: None,
+                fault_byte: None,
+                require_cube_present: false,
+                dropped_move_check: DroppedMoveCheck::default(),
+                inactivity_timeout: None,
+                reuse_discovery: false,
+                measure_duration: false,
+                fill_byte: FILL_BYTE,
+                wait_for_idle_on_connect: false,
+                sleeper: Arc::new(TokioSleeper),
+                on_move_sent: None,
+                before_chunk: None,
+                after_chunk: None,
+                pause_handle: PauseHandle::new(),
+            },
         })
     }
 
+    /// Makes the name only match the robot with the same case. By default the name is compared
+    /// ignoring case, as some platforms report it differently, e.g. `gan-a7f13` for `GAN-a7f13`.
+    pub fn with_exact_name_case(mut self, exact_name_case: bool) -> Self {
+        self.state.exact_name_case = exact_name_case;
+        self
+    }
+
+    /// Sets the signal strength in dBm below which a warning is logged when connecting, -85 dBm
+    /// by default. See [`GanRobotController::is_weak_signal`].
+    pub fn with_low_rssi_threshold(mut self, low_rssi_threshold: i16) -> Self {
+        self.state.low_rssi_threshold = low_rssi_threshold;
+        self
+    }
+
+    /// Sets the service the characteristics are expected on, [`GAN_ROBOT_SERVICE`] by default.
+    /// Characteristics on that service are preferred, and a warning is logged if one is only
+    /// found on another service. `None` searches all services alike.
+    pub fn with_service(mut self, service: Option<&str>) -> anyhow::Result<Self> {
+        self.state.service = service.map(Uuid::parse_str).transpose()?;
+        Ok(self)
+    }
+
+    /// Sets a characteristic the firmware reports its move buffer capacity on, as a single byte.
+    /// No known firmware has one, so this is unset by default. If the characteristic is found,
+    /// the capacity is read on connect and lowers the maximum moves per write when it is smaller,
+    /// see [`GanRobotController::query_max_moves`].
+    pub fn with_capacity_characteristic(mut self, uuid: &str) -> anyhow::Result<Self> {
+        self.state.capacity_characteristic = Some(Uuid::parse_str(uuid)?);
+        Ok(self)
+    }
+
+    /// Sets a characteristic the firmware reports the stickers of the cube on, for a robot that
+    /// can see the cube. No known firmware has one, so this is unset by default. See
+    /// [`GanRobotController::read_cube_state`] for the format.
+    pub fn with_cube_state_characteristic(mut self, uuid: &str) -> anyhow::Result<Self> {
+        self.state.cube_state_characteristic = Some(Uuid::parse_str(uuid)?);
+        Ok(self)
+    }
+
+    /// Caps the number of moves accepted in a single command, regardless of
+    /// [`MAX_MOVES_PER_WRITE`]. Longer sequences are rejected before anything is written.
+    pub fn with_max_sequence_len(mut self, max_sequence_len: Option<usize>) -> Self {
+        self.state.max_sequence_len = max_sequence_len;
+        self
+    }
+
+    /// Sets how many moves are sent in a single write, for firmware with a queue smaller than
+    /// the [`MAX_MOVES_PER_WRITE`] the write buffer can hold. Longer sequences are split into
+    /// chunks of this size.
+    pub fn with_max_moves_per_write(mut self, max_moves_per_write: usize) -> anyhow::Result<Self> {
+        if !(1..=MAX_MOVES_PER_WRITE).contains(&max_moves_per_write) {
+            anyhow::bail!(
+                "Invalid maximum moves per write: {max_moves_per_write}. Must be between 1 and {MAX_MOVES_PER_WRITE}"
+            );
+        }
+        self.state.max_moves_per_write = max_moves_per_write;
+        Ok(self)
+    }
+
+    /// Sets how sequences are split into writes, see [`BatchBy`]. Defaults to as many moves as
+    /// fit in a write.
+    pub fn with_batch_by(mut self, batch_by: BatchBy) -> anyhow::Result<Self> {
+        if batch_by == BatchBy::MoveCount(0) || batch_by == BatchBy::Duration(Duration::ZERO) {
+            anyhow::bail!("Invalid batching: {batch_by:?}. Must be positive");
+        }
+        self.state.batch_by = batch_by;
+        Ok(self)
+    }
+
+    /// Sets how the robot is placed relative to the user. Moves given to [`Self::do_moves`] and
+    /// friends are remapped accordingly, raw moves are not.
+    pub fn with_orientation(mut self, orientation: Orientation) -> Self {
+        self.state.orientation = orientation;
+        self
+    }
+
+    /// Sets how often a write is repeated when the remaining count shows it did not arrive at
+    /// all. Defaults to 0, which only logs a warning.
+    pub fn with_write_retries(mut self, write_retries: usize) -> Self {
+        self.state.write_retries = write_retries;
+        self
+    }
+
+    /// Makes [`GanRobotController::do_moves`] wait for a sequence that fits in a single write
+    /// by sleeping its full estimated duration and checking the remaining count once, instead
+    /// of polling it from 75% of the estimate on. Slightly faster for short sequences, but a
+    /// robot slower than the estimate is then polled as usual. Off by default.
+    pub fn with_settle_at_end(mut self, settle_at_end: bool) -> Self {
+        self.state.settle_at_end = settle_at_end;
+        self
+    }
+
+    /// Sets how long moves are expected to take, see [`TimingConfig`].
+    pub fn with_timing(mut self, timing: TimingConfig) -> Self {
+        self.state.timing = timing;
+        self
+    }
+
+    /// Makes [`GanRobotController::do_moves`] and [`GanRobotController::drive_from_channel`]
+    /// merge the moves with [`cancel_moves`] before sending them, so that e.g. `R R R` is done
+    /// as `R'`, saving time and wear. Off by default, as the hooks then see other moves than
+    /// were given.
+    pub fn with_merge_moves(mut self, merge_moves: bool) -> Self {
+        self.state.merge_moves = merge_moves;
+        self
+    }
+
+    /// Sets where the status payload says whether a cube is in the robot: the bits of `mask` in
+    /// the byte at `index`, set when the cube is present. See [`RobotStatus::cube_present`].
+    pub fn with_cube_presence_flag(mut self, index: usize, mask: u8) -> Self {
+        self.state.cube_presence_flag = Some((index, mask));
+        self
+    }
+
+    /// Sets the byte of the status payload at `index` to hold [`FaultFlags`]. Waiting for moves
+    /// then fails with [`ControllerError::RobotFault`] as soon as a bit is set, instead of
+    /// waiting for a robot that stopped turning until it seems stuck. Faults are not decoded by
+    /// default, as no known firmware reports them.
+    pub fn with_fault_byte(mut self, index: usize) -> Self {
+        self.state.fault_byte = Some(index);
+        self
+    }
+
+    /// Makes every write fail with [`ControllerError::CubeNotPresent`] when the status says the
+    /// cube was taken out, instead of turning an empty robot. This needs
+    /// [`Self::with_cube_presence_flag`], as moves are sent as usual when the status does not
+    /// say. Off by default.
+    pub fn with_require_cube_present(mut self, require_cube_present: bool) -> Self {
+        self.state.require_cube_present = require_cube_present;
+        self
+    }
+
+    /// Sets how long to wait after a write before reading the remaining count, 30 ms by
+    /// default. Some robots still report the previous count right after a write, which makes a
+    /// sequence look done or dropped before it started.
+    pub fn with_status_read_delay(mut self, status_read_delay: Duration) -> Self {
+        self.state.status_read_delay = status_read_delay;
+        self
+    }
+
+    /// Sets how moves are written, [`WriteType::WithoutResponse`] by default. Writes with
+    /// response are confirmed by the robot, which is slower but more reliable on a flaky link,
+    /// and need a move characteristic that supports them.
+    pub fn with_write_type(mut self, write_type: WriteType) -> Self {
+        self.state.write_type = write_type;
+        self
+    }
+
+    /// Sets what to do when writing a chunk of a sequence fails, see [`ErrorPolicy`]. Unlike
+    /// [`Self::with_write_retries`], which repeats writes that silently did not arrive, this
+    /// handles writes that fail with an error. Single moves always fail right away.
+    pub fn with_error_policy(mut self, error_policy: ErrorPolicy) -> Self {
+        self.state.error_policy = error_policy;
+        self
+    }
+
+    /// Sets what to do when the robot got fewer moves than were sent, see [`DroppedMoveCheck`].
+    pub fn with_dropped_move_check(mut self, dropped_move_check: DroppedMoveCheck) -> Self {
+        self.state.dropped_move_check = dropped_move_check;
+        self
+    }
+
+    /// Sets a hook called with each move right before it is written, e.g. to echo the moves as
+    /// they happen. The moves are as sent to the robot, i.e. after orientation remapping, and
+    /// double turns are reported without prime.
+    pub fn with_on_move_sent(
+        mut self,
+        on_move_sent: impl Fn(FaceRotation) + Send + Sync + 'static,
+    ) -> Self {
+        self.state.on_move_sent = Some(Arc::new(on_move_sent));
+        self
+    }
+
+    /// Sets a hook called with the moves of each chunk right before it is written by
+    /// [`GanRobotController::do_moves`] and its variants taking a sequence, e.g. to drive
+    /// hardware in between. The moves are as sent to the robot, like for
+    /// [`Self::with_on_move_sent`]. The hook runs synchronously in the middle of the sequence,
+    /// so it must be quick and must not call back into the controller.
+    pub fn with_before_chunk(
+        mut self,
+        before_chunk: impl Fn(&[FaceRotation]) + Send + Sync + 'static,
+    ) -> Self {
+        self.state.before_chunk = Some(Arc::new(before_chunk));
+        self
+    }
+
+    /// Sets a hook called with the moves of each chunk once the robot finished it, with the
+    /// same constraints as [`Self::with_before_chunk`]. It is not called for a failed chunk.
+    pub fn with_after_chunk(
+        mut self,
+        after_chunk: impl Fn(&[FaceRotation]) + Send + Sync + 'static,
+    ) -> Self {
+        self.state.after_chunk = Some(Arc::new(after_chunk));
+        self
+    }
+
+    /// Pauses and resumes with `pause_handle` instead of a handle of its own, so that it can be
+    /// used before connecting, e.g. in a chunk hook. Here, the sequence pauses after its first
+    /// chunk:
+    ///
+    #[cfg_attr(feature = "test-util", doc = "```")]
+    #[cfg_attr(not(feature = "test-util"), doc = "```ignore")]
+    /// use std::time::Duration;
+    ///
+    /// use lib::{moves, BatchBy, DroppedMoveCheck, FakePeripheral, GanRobotController, PauseHandle};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let move_uuid = "0000fff3-0000-1000-8000-00805f9b34fb";
+    /// let status_uuid = "0000fff2-0000-1000-8000-00805f9b34fb";
+    /// let robot = FakePeripheral::new(move_uuid, status_uuid)?;
+    /// robot.push_read(robot.status_characteristic(), vec![0]);
+    /// let pause = PauseHandle::new();
+    /// let controller = GanRobotController::try_new("GAN", move_uuid, status_uuid)?
+    ///     .with_dropped_move_check(DroppedMoveCheck::Off)
+    ///     .with_batch_by(BatchBy::MoveCount(1))?
+    ///     .with_pause_handle(pause.clone())
+    ///     .with_after_chunk({
+    ///         let pause = pause.clone();
+    ///         move |_| pause.pause()
+    ///     })
+    ///     .from_peripheral(robot.clone())
+    ///     .await?;
+    ///
+    /// let moves = moves!("R F");
+    /// let mut doing = std::pin::pin!(controller.do_moves(&moves));
+    /// tokio::select! {
+    ///     result = &mut doing => panic!("Finished while paused: {result:?}"),
+    ///     () = tokio::time::sleep(Duration::from_millis(500)) => {}
+    /// }
+    /// assert!(pause.is_paused());
+    /// assert_eq!(robot.writes(robot.move_characteristic()).len(), 1);
+    ///
+    /// pause.resume();
+    /// doing.await?;
+    /// assert_eq!(robot.writes(robot.move_characteristic()).len(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_pause_handle(mut self, pause_handle: PauseHandle) -> Self {
+        self.state.pause_handle = pause_handle;
+        self
+    }
+
+    /// Disconnects from the robot after `timeout` without moves, e.g. so that a forgotten
+    /// connection to a shared robot does not keep others from connecting. The timer restarts
+    /// whenever moves are done and does not run while they are in progress. It runs as a task on
+    /// the Tokio runtime the controller connects on, and is not started without one.
+    ///
+    /// The controller stays [`Connected`] in the type system after that, so its owner learns
+    /// about it from [`GanRobotController::is_disconnected_for_inactivity`], by awaiting
+    /// [`GanRobotController::disconnected_for_inactivity`], or from the moves failing with
+    /// [`ControllerError::DisconnectedForInactivity`]. Connect again to continue.
+    pub fn with_inactivity_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.state.inactivity_timeout = timeout;
+        self
+    }
+
+    /// Makes connecting wait for moves left from an earlier session to finish, e.g. after a crash
+    /// in the middle of a sequence, so that the first moves start from a robot at rest. This
+    /// reads the status when connecting, which is why it is off by default.
+    pub fn with_wait_for_idle_on_connect(mut self, wait_for_idle_on_connect: bool) -> Self {
+        self.state.wait_for_idle_on_connect = wait_for_idle_on_connect;
+        self
+    }
+
+    /// Sets what the bytes of a write after the moves are set to, `0xff` by default, for
+    /// firmware that would take `0xff` for moves. The moves themselves are still terminated by a
+    /// `0xf` nibble.
+    ///
+    #[cfg_attr(feature = "test-util", doc = "```")]
+    #[cfg_attr(not(feature = "test-util"), doc = "```ignore")]
+    /// use lib::{moves, DroppedMoveCheck, FakePeripheral, GanRobotController};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let move_uuid = "0000fff3-0000-1000-8000-00805f9b34fb";
+    /// let status_uuid = "0000fff2-0000-1000-8000-00805f9b34fb";
+    /// let robot = FakePeripheral::new(move_uuid, status_uuid)?;
+    /// robot.push_read(robot.status_characteristic(), vec![0]);
+    /// let controller = GanRobotController::try_new("GAN", move_uuid, status_uuid)?
+    ///     .with_dropped_move_check(DroppedMoveCheck::Off)
+    ///     .with_fill_byte(0x00)
+    ///     .from_peripheral(robot.clone())
+    ///     .await?;
+    ///
+    /// controller.do_moves(&moves!("R F2 D'")).await?;
+    /// let mut expected = vec![0x04, 0x8f];
+    /// expected.resize(18, 0x00);
+    /// assert_eq!(robot.writes(robot.move_characteristic()), [expected]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_fill_byte(mut self, fill_byte: u8) -> Self {
+        self.state.fill_byte = fill_byte;
+        self
+    }
+
+    /// Makes [`GanRobotController::scramble`] measure how long the moves took, see
+    /// [`ScrambleReport::actual`]. Off by default.
+    pub fn with_measure_duration(mut self, measure_duration: bool) -> Self {
+        self.state.measure_duration = measure_duration;
+        self
+    }
+
+    /// Skips service discovery when connecting to a peripheral whose services were already
+    /// discovered in this session, which makes reconnecting faster. Reconnect by passing
+    /// [`GanRobotController::peripheral`] to [`Self::from_peripheral`]. Off by default.
+    ///
+    /// The services are kept by the peripheral itself, so a peripheral found by a new scan, as
+    /// with [`Self::try_connect`], is always discovered in full. Known services are only used if
+    /// they still contain the move characteristic and the status can be read with them;
+    /// otherwise, e.g. after a firmware update changed them, they are discovered again.
+    pub fn with_reuse_discovery(mut self, reuse_discovery: bool) -> Self {
+        self.state.reuse_discovery = reuse_discovery;
+        self
+    }
+
+    /// Replaces the [`TokioSleeper`] used for all waiting, e.g. to run on another executor.
+    pub fn with_sleeper(mut self, sleeper: impl Sleeper + 'static) -> Self {
+        self.state.sleeper = Arc::new(sleeper);
+        self
+    }
+
     pub async fn try_connect(self) -> anyhow::Result<GanRobotController<Connected>> {
-        let manager = Manager::new().await?;
-        let central = Self::get_central(&manager).await;
+        let gan_robot = self.scan().await?;
+        self.from_peripheral(gan_robot).await
+    }
+
+    /// Connects to the robot only to list its services and characteristics, see
+    /// [`GanRobotController::describe_gatt`]. Unlike [`Self::try_connect`], this works with
+    /// wrong characteristic UUIDs, so it helps to find the right ones for another model.
+    pub async fn try_describe_gatt(self) -> anyhow::Result<Vec<ServiceInfo>> {
+        let gan_robot = self.scan().await?;
+        trace!("Discovering services");
+        gan_robot.discover_services().await?;
+        let services = describe_services(&gan_robot);
+        trace!("Disconnecting");
+        gan_robot.disconnect().await?;
+        Ok(services)
+    }
 
-        let mut events = central.events().await?;
-        info!("Scanning for GAN robot");
-        central.start_scan(ScanFilter::default()).await?;
+    /// Scans for the robot and connects to it.
+    async fn scan(&self) -> anyhow::Result<Peripheral> {
+        let manager = Manager::new().await.map_err(bluetooth_error)?;
+        let (central, mut events) = Self::get_central(&manager).await?;
 
         while let Some(event) = events.next().await {
             if let CentralEvent::DeviceDiscovered(id) = event {
-                if let Some(gan_robot) = Self::find_gan_robot(&central, &id, &self.name).await? {
-                    gan_robot.connect().await?;
-                    let move_characteristic =
-                        Self::find_move_characteristic(&gan_robot, &self.move_characteristic)
-                            .await?;
-                    let status_characteristic =
-                        Self::find_move_characteristic(&gan_robot, &self.status_characteristic)
-                            .await?;
-                    return Ok(GanRobotController {
-                        state: Connected {
-                            gan_robot,
-                            move_characteristic,
-                            status_characteristic,
-                            face_rotation_map: FaceRotationMap::new(),
-                        },
-                    });
-                } else {
-                    continue;
+                if let Some(gan_robot) =
+                    Self::find_gan_robot(&central, &id, |local_name| self.matches_name(local_name))
+                        .await?
+                {
+                    return Ok(gan_robot);
                 }
             }
         }
@@ -102,127 +791,2034 @@ impl GanRobotController<Uninitialized> {
         Err(anyhow::anyhow!("GAN robot not found"))
     }
 
-    async fn get_central(manager: &Manager) -> Adapter {
-        let adapters = manager.adapters().await.unwrap();
-        adapters.into_iter().next().unwrap()
+    /// Sets the controller up on an already found peripheral instead of scanning for one, e.g.
+    /// a [`crate::FakePeripheral`] in tests. Connects to it if needed.
+    pub async fn from_peripheral<P: api::Peripheral + 'static>(
+        self,
+        gan_robot: P,
+    ) -> anyhow::Result<GanRobotController<Connected<P>>> {
+        if !gan_robot.is_connected().await? {
+            trace!("Connecting");
+            gan_robot.connect().await?;
+        }
+        if self.reuse_discovery && self.known_services_work(&gan_robot).await {
+            trace!("Reusing the services discovered before");
+        } else {
+            trace!("Discovering services");
+            gan_robot.discover_services().await?;
+        }
+        let move_characteristic = Self::find_characteristic(
+            &gan_robot,
+            self.service.as_ref(),
+            &self.move_characteristic,
+        )?;
+        let status_characteristic = Self::find_characteristic(
+            &gan_robot,
+            self.service.as_ref(),
+            &self.status_characteristic,
+        )
+        .inspect_err(|e| {
+            warn!("Status not available, moves are timed by their estimated duration: {e}")
+        })
+        .ok();
+        let write_property = match self.write_type {
+            WriteType::WithResponse => CharPropFlags::WRITE,
+            WriteType::WithoutResponse => CharPropFlags::WRITE_WITHOUT_RESPONSE,
+        };
+        Self::check_properties(&move_characteristic, write_property)?;
+        if let Some(status_characteristic) = &status_characteristic {
+            Self::check_properties(status_characteristic, CharPropFlags::READ)?;
+        }
+        let capacity_characteristic = self.capacity_characteristic.and_then(|uuid| {
+            Self::find_characteristic(&gan_robot, self.service.as_ref(), &uuid)
+                .inspect_err(|e| warn!("Move buffer capacity not available: {e}"))
+                .ok()
+        });
+        let cube_state_characteristic = self.cube_state_characteristic.and_then(|uuid| {
+            Self::find_characteristic(&gan_robot, self.service.as_ref(), &uuid)
+                .inspect_err(|e| warn!("Cube state not available: {e}"))
+                .ok()
+        });
+        let device_info = Self::read_device_info(&gan_robot).await;
+        let mut controller = GanRobotController {
+            state: Connected {
+                gan_robot,
+                device_info,
+                low_rssi_threshold: self.low_rssi_threshold,
+                move_characteristic,
+                status_characteristic,
+                capacity_characteristic,
+                cube_state_characteristic,
+                face_rotation_map: FaceRotationMap::new(),
+                max_sequence_len: self.max_sequence_len,
+                max_moves_per_write: self.max_moves_per_write,
+                batch_by: self.batch_by,
+                orientation: self.orientation,
+                write_retries: self.write_retries,
+                write_type: self.write_type,
+                status_read_delay: self.status_read_delay,
+                error_policy: self.error_policy,
+                settle_at_end: self.settle_at_end,
+                timing: self.timing.clone(),
+                merge_moves: self.merge_moves,
+                cube_presence_flag: self.cube_presence_flag,
+                fault_byte: self.fault_byte,
+                require_cube_present: self.require_cube_present,
+                dropped_move_check: self.dropped_move_check,
+                measure_duration: self.measure_duration,
+                fill_byte: self.fill_byte,
+                sleeper: self.sleeper.clone(),
+                on_move_sent: self.on_move_sent.clone(),
+                before_chunk: self.before_chunk.clone(),
+                after_chunk: self.after_chunk.clone(),
+                pause_handle: self.pause_handle.clone(),
+                u_d_swapped: AtomicBool::new(false),
+                history: std::sync::Mutex::new(vec![]),
+                busy_until: std::sync::Mutex::new(Instant::now()),
+                executing: Mutex::new(()),
+                watchdog: None,
+            },
+        };
+        match controller.rssi().await {
+            Ok(rssi) if controller.is_weak_signal(rssi) => warn!(
+                "Weak signal: {rssi} dBm. Writes may be dropped, so move the robot closer or use a better Bluetooth adapter"
+            ),
+            Ok(rssi) => info!("Signal strength: {rssi} dBm"),
+            Err(_) => {}
+        }
+        if let Some(capacity) = controller.query_max_moves().await {
+            controller.state.max_moves_per_write =
+                controller.max_moves_per_write.min(capacity.min(MAX_MOVES_PER_WRITE));
+        }
+        if self.wait_for_idle_on_connect && controller.status_characteristic.is_some() {
+            let remaining_moves = controller.get_remaining_moves().await?;
+            if remaining_moves > 0 {
+                warn!(
+                    "GAN robot is still doing {remaining_moves} moves, waiting for them to finish"
+                );
+                controller.wait_until_idle().await?;
+            }
+        }
+        controller.state.watchdog = self.inactivity_timeout.and_then(|timeout| {
+            Watchdog::spawn(controller.gan_robot.clone(), timeout, self.sleeper.clone())
+        });
+        Ok(controller)
+    }
+
+    /// Starts scanning on the first adapter that works. Adapters that are powered off or blocked
+    /// are still listed, e.g. on Linux, but fail to start a scan and are skipped.
+    async fn get_central(
+        manager: &Manager,
+    ) -> anyhow::Result<(Adapter, Pin<Box<dyn Stream<Item = CentralEvent> + Send>>)> {
+        let mut permission_denied = false;
+        for adapter in manager.adapters().await.map_err(bluetooth_error)? {
+            let info = adapter.adapter_info().await.unwrap_or_else(|e| e.to_string());
+            trace!("Starting scan on adapter {info}");
+            let started = match adapter.events().await {
+                Ok(events) => adapter.start_scan(ScanFilter::default()).await.map(|()| events),
+                Err(e) => Err(e),
+            };
+            match started {
+                Ok(events) => {
+                    info!("Scanning for GAN robot on adapter {info}");
+                    return Ok((adapter, events));
+                }
+                Err(e) => {
+                    permission_denied |= matches!(e, btleplug::Error::PermissionDenied);
+                    warn!("Skipping adapter {info}: {e}");
+                }
+            }
+        }
+        if permission_denied {
+            return Err(bluetooth_error(btleplug::Error::PermissionDenied));
+        }
+        anyhow::bail!("No working Bluetooth adapter found")
+    }
+
+    fn matches_name(&self, local_name: &str) -> bool {
+        if self.exact_name_case {
+            local_name == self.name
+        } else {
+            local_name.eq_ignore_ascii_case(&self.name)
+        }
     }
 
     async fn find_gan_robot(
         central: &Adapter,
         id: &PeripheralId,
-        name: &str,
+        matches_name: impl Fn(&str) -> bool,
     ) -> anyhow::Result<Option<Peripheral>> {
         let peripheral = central.peripheral(id).await?;
-        let properties = peripheral.properties().await?;
-        let local_name = properties.and_then(|p| p.local_name).unwrap_or_default();
-        if local_name == name {
+        let properties = peripheral.properties().await?.unwrap_or_default();
+        let local_name = properties.local_name.unwrap_or_default();
+        trace!("Discovered {id:?} {local_name:?}");
+        if matches_name(&local_name) {
+            trace!("Stopping scan");
             central.stop_scan().await?;
+            trace!("Connecting to {id:?}");
             peripheral.connect().await?;
-            info!("Connected: {id:?} {name}");
+            info!("Connected: {id:?} {local_name}");
             return Ok(Some(peripheral));
         }
         Ok(None)
     }
 
-    async fn find_move_characteristic(
-        peripheral: &Peripheral,
+    fn check_properties(
+        characteristic: &Characteristic,
+        required: CharPropFlags,
+    ) -> anyhow::Result<()> {
+        info!("Characteristic {}: {:?}", characteristic.uuid, characteristic.properties);
+        if !characteristic.properties.contains(required) {
+            anyhow::bail!(
+                "Characteristic {} lacks the required properties: {:?}",
+                characteristic.uuid,
+                required.difference(characteristic.properties)
+            );
+        }
+        Ok(())
+    }
+
+    /// Reads what the robot advertises and its model number, if it has a device information
+    /// service. Anything that cannot be read is left out.
+    async fn read_device_info(gan_robot: &impl api::Peripheral) -> DeviceInfo {
+        let properties = gan_robot.properties().await.ok().flatten().unwrap_or_default();
+        let mut model_number = None;
+        if let Ok(characteristic) = Uuid::parse_str(MODEL_NUMBER_CHARACTERISTIC)
+            .map_err(anyhow::Error::from)
+            .and_then(|uuid| Self::find_characteristic(gan_robot, None, &uuid))
+        {
+            model_number = match gan_robot.read(&characteristic).await {
+                Ok(value) => {
+                    Some(String::from_utf8_lossy(&value).trim_end_matches('\0').to_string())
+                }
+                Err(e) => {
+                    warn!("Failed to read the model number: {e}");
+                    None
+                }
+            };
+        }
+        DeviceInfo {
+            local_name: properties.local_name,
+            model_number,
+            manufacturer_data: properties.manufacturer_data,
+            rssi: properties.rssi,
+        }
+    }
+
+    /// Returns `true` if the services `gan_robot` knows from an earlier discovery contain the
+    /// move and status characteristics and the status can be read, see
+    /// [`Self::with_reuse_discovery`].
+    async fn known_services_work(&self, gan_robot: &impl api::Peripheral) -> bool {
+        let service = self.service.as_ref();
+        if Self::find_characteristic(gan_robot, service, &self.move_characteristic).is_err() {
+            return false;
+        }
+        match Self::find_characteristic(gan_robot, service, &self.status_characteristic) {
+            Ok(status_characteristic) => gan_robot.read(&status_characteristic).await.is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn find_characteristic(
+        peripheral: &impl api::Peripheral,
+        service: Option<&Uuid>,
         uuid: &Uuid,
     ) -> anyhow::Result<Characteristic> {
-        peripheral.discover_services().await?;
-        for service in peripheral.services() {
-            for characteristic in service.characteristics {
-                if characteristic.uuid == *uuid {
-                    return Ok(characteristic);
-                }
-            }
+        let mut characteristics = peripheral
+            .services()
+            .into_iter()
+            .flat_map(|s| s.characteristics)
+            .filter(|c| c.uuid == *uuid)
+            .collect::<Vec<_>>();
+        let Some(service) = service else {
+            return characteristics
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Characteristic {uuid} not found"));
+        };
+
+        if let Some(i) = characteristics.iter().position(|c| c.service_uuid == *service) {
+            return Ok(characteristics.swap_remove(i));
         }
-        Err(anyhow::anyhow!("Move characteristic not found"))
+        let characteristic = characteristics
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Characteristic {uuid} not found"))?;
+        warn!(
+            "Characteristic {uuid} found on service {} instead of {service}",
+            characteristic.service_uuid
+        );
+        Ok(characteristic)
     }
 }
 
-impl GanRobotController<Connected> {
-    pub async fn scramble(&self, num_moves: usize) -> anyhow::Result<()> {
+impl<P: api::Peripheral + 'static> GanRobotController<Connected<P>> {
+    /// Scrambles with `num_moves` random moves and returns what was done.
+    pub async fn scramble(&self, num_moves: usize) -> anyhow::Result<ScrambleReport> {
+        self.scramble_from(num_moves, &self.face_rotation_map).await
+    }
+
+    /// Like [`Self::scramble`], but takes the moves from `source` instead of picking them at
+    /// random. Invalid moves from the source are rejected before anything is sent.
+    ///
+    #[cfg_attr(feature = "test-util", doc = "```")]
+    #[cfg_attr(not(feature = "test-util"), doc = "```ignore")]
+    /// use std::time::Duration;
+    ///
+    /// use lib::{moves, DroppedMoveCheck, FaceRotation, FakePeripheral, GanRobotController, ScrambleSource};
+    ///
+    /// struct Fixed;
+    ///
+    /// impl ScrambleSource for Fixed {
+    ///     fn next_scramble(&self, _length: usize) -> Vec<FaceRotation> {
+    ///         moves!("R F2 D'")
+    ///     }
+    /// }
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let move_uuid = "0000fff3-0000-1000-8000-00805f9b34fb";
+    /// let status_uuid = "0000fff2-0000-1000-8000-00805f9b34fb";
+    /// let robot = FakePeripheral::new(move_uuid, status_uuid)?;
+    /// robot.push_read(robot.status_characteristic(), vec![0]);
+    /// let controller = GanRobotController::try_new("GAN", move_uuid, status_uuid)?
+    ///     .with_dropped_move_check(DroppedMoveCheck::Off)
+    ///     .with_measure_duration(true)
+    ///     .from_peripheral(robot)
+    ///     .await?;
+    ///
+    /// let report = controller.scramble_from(3, &Fixed).await?;
+    /// assert_eq!(report.moves, moves!("R F2 D'"));
+    /// assert_eq!(report.estimated, Duration::from_millis(550));
+    /// assert!(report.actual.is_some());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn scramble_from(
+        &self,
+        num_moves: usize,
+        source: &dyn ScrambleSource,
+    ) -> anyhow::Result<ScrambleReport> {
+        info!("Scrambling with {num_moves} moves");
+        let moves = source.next_scramble(num_moves);
+        if moves.contains(&FaceRotation::Invalid) {
+            anyhow::bail!("The scramble source returned an invalid move");
+        }
+        let estimated = self.estimated_duration(&moves);
+        let start = Instant::now();
+        self.do_moves(&moves).await?;
+        let actual = self.measure_duration.then(|| start.elapsed());
+        Ok(ScrambleReport { moves, estimated, actual })
+    }
+
+    /// Estimates how long the robot takes for `moves` as they would be sent, i.e. after
+    /// orientation remapping and merging, with the [`TimingConfig`] of the controller, not
+    /// counting BLE latency.
+    pub fn estimated_duration(&self, moves: &[FaceRotation]) -> Duration {
+        let ms = self
+            .encode(moves)
+            .iter()
+            .map(|&m| self.timing.move_duration(m))
+            .sum::<usize>();
+        Duration::from_millis(ms as u64)
+    }
+
+    /// Scrambles with `num_moves` random moves like [`Self::scramble`] and returns the moves
+    /// together with the state they leave a solved cube in, e.g. to hand out the scramble as a
+    /// quiz. The state is computed with [`net_effect`], not read from the robot.
+    pub async fn scramble_and_capture(
+        &self,
+        num_moves: usize,
+    ) -> anyhow::Result<(Vec<FaceRotation>, CubeState)> {
         info!("Scrambling with {num_moves} moves");
         let moves = self.face_rotation_map.get_random_moves(num_moves);
         self.do_moves(&moves).await?;
+        let state = net_effect(&moves);
+        Ok((moves, state))
+    }
+
+    /// Undoes each scramble in the text file at `path`, one per line, by doing its inverse, e.g.
+    /// to return a cube to solved after each scramble of a batch. The scrambles are undone in
+    /// the order of the file, each on its own, and may be numbered like in a TNoodle export and
+    /// contain U moves. A line that does not parse is reported and skipped, or fails the call
+    /// before anything is done if `strict` is set. Returns the number of scrambles undone.
+    pub async fn do_inverse_scramble_file(
+        &self,
+        path: impl AsRef<Path>,
+        strict: bool,
+    ) -> anyhow::Result<usize> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        let mut solutions = vec![];
+        for (i, line) in content.lines().enumerate() {
+            let line = strip_numbering(line);
+            if line.is_empty() {
+                continue;
+            }
+            let moves = normalize_notation(line).map(|s| parse_moves_translating_u(&s));
+            let error = match moves {
+                Ok(moves) if !moves.contains(&FaceRotation::Invalid) => {
+                    solutions.push(invert_sequence(&moves));
+                    continue;
+                }
+                Ok(_) => anyhow::anyhow!("Invalid move"),
+                Err(e) => e,
+            };
+            if strict {
+                anyhow::bail!("{}:{}: {error}", path.display(), i + 1);
+            }
+            warn!("Skipping {}:{}: {error}", path.display(), i + 1);
+        }
+        for (i, solution) in solutions.iter().enumerate() {
+            info!("Undoing scramble {}/{}", i + 1, solutions.len());
+            self.do_moves(solution).await?;
+        }
+        Ok(solutions.len())
+    }
+
+    /// Does the superflip on a solved cube and returns the moves done: the 20 move sequence
+    /// `U R2 F B R B2 R U2 L B2 R U' D' R2 F R' L B2 U2 F2`, with the U moves translated, see
+    /// [`crate::parse_moves_translating_u`]. Before that, the translated moves are checked to
+    /// lead to [`CubeState::superflip`], which is built independently of the sequence, and an
+    /// error is returned if they do not.
+    pub async fn superflip(&self) -> anyhow::Result<Vec<FaceRotation>> {
+        let moves = pattern("superflip").unwrap_or_default();
+        if net_effect(&moves) != CubeState::superflip() {
+            anyhow::bail!("The superflip moves do not lead to the superflip");
+        }
+        info!("Doing the superflip");
+        self.do_moves(&moves).await?;
+        Ok(moves)
+    }
+
+    /// Does the named pattern from [`PATTERNS`], assuming a solved cube, and returns the moves
+    /// done. Before that, the moves are verified to lead to the pattern with [`CubeState`].
+    pub async fn set_pattern(&self, name: &str) -> anyhow::Result<Vec<FaceRotation>> {
+        let (Some(moves), Some(target)) = (pattern(name), pattern_state(name)) else {
+            anyhow::bail!(
+                "Unknown pattern: {name}. Available patterns: {}",
+                PATTERNS.iter().map(|(n, _)| *n).collect::<Vec<_>>().join(", ")
+            );
+        };
+        let mut state = CubeState::solved();
+        state.apply_all(&moves);
+        if state != target {
+            anyhow::bail!("The moves for pattern {name} do not lead to the pattern");
+        }
+        info!("Setting pattern {name}");
+        self.do_moves(&moves).await?;
+        Ok(moves)
+    }
+
+    /// Does the pattern from [`PATTERNS`] which turns a solved cube into `target`, and returns
+    /// the moves done.
+    pub async fn apply_to_pattern(&self, target: &CubeState) -> anyhow::Result<Vec<FaceRotation>> {
+        let Some((name, _)) = PATTERNS
+            .iter()
+            .find(|(name, _)| pattern_state(name).as_ref() == Some(target))
+        else {
+            anyhow::bail!("No known pattern leads to the target state");
+        };
+        self.set_pattern(name).await
+    }
+
+    /// Keeps doing single random moves every `interval` until `cancel` completes. Consecutive
+    /// moves never turn the same face. A move in progress is always finished before stopping.
+    pub async fn wander(
+        &self,
+        interval: Duration,
+        cancel: impl Future<Output = ()>,
+    ) -> anyhow::Result<()> {
+        info!("Wandering with a move every {} ms", interval.as_millis());
+        let mut cancel = std::pin::pin!(cancel);
+        let mut previous = None;
+        loop {
+            let m = self.face_rotation_map.get_random_move_avoiding(previous);
+            self.do_moves(&[m]).await?;
+            previous = Some(m);
+            let sleep = std::pin::pin!(self.sleeper.sleep(interval));
+            if let Either::Right(_) = futures::future::select(sleep, cancel.as_mut()).await {
+                break;
+            }
+        }
+        info!("Stopped wandering");
         Ok(())
     }
 
+    /// Does the given moves, skipping invalid ones. Sequences longer than the maximum moves per
+    /// write, see [`GanRobotController::with_max_moves_per_write`], or than allowed by
+    /// [`GanRobotController::with_batch_by`], are split into several writes.
+    ///
+    /// Concurrent calls on a shared controller, to this or any other method doing moves, run one
+    /// after another, so that their writes never interleave.
     pub async fn do_moves(&self, moves: &[FaceRotation]) -> anyhow::Result<()> {
+        let _executing = self.execute().await?;
+        self.do_moves_exclusive(moves).await
+    }
+
+    /// [`Self::do_moves`] for callers already holding `executing`.
+    async fn do_moves_exclusive(&self, moves: &[FaceRotation]) -> anyhow::Result<()> {
+        info!(
+            "Doing moves: {}",
+            moves.iter().map(|m| m.to_string()).collect::<Vec<String>>().join(" ")
+        );
+        let moves = self.encode(moves);
+        self.check_sequence_len(moves.len())?;
+        let lens = self.chunk_lens(&moves);
+        if self.settle_at_end && lens.len() == 1 {
+            self.wait_while_paused(0).await;
+            self.call_chunk_hook(&self.before_chunk, &moves);
+            if !self.write_chunk(&moves).await? {
+                return Ok(());
+            }
+            let sleep_duration = moves.iter().map(|&m| self.timing.move_duration(m)).sum::<usize>();
+            self.sleeper.sleep(Duration::from_millis(sleep_duration as u64)).await;
+            let result = self.wait_until_idle().await;
+            self.check_disconnected(result, moves.len()).await?;
+            self.call_chunk_hook(&self.after_chunk, &moves);
+            return Ok(());
+        }
+        let mut sent = 0;
+        for (i, chunk) in split_by(&moves, &lens).into_iter().enumerate() {
+            self.wait_while_paused(i).await;
+            self.do_chunk(chunk, sent, CompletionPredicate::Drained).await?;
+            sent += chunk.len();
+        }
+        Ok(())
+    }
+
+    /// Like [`Self::do_moves`], but takes any iterator of moves, e.g. a filtered chain, so that
+    /// callers do not need to collect it first.
+    pub async fn do_moves_iter(
+        &self,
+        moves: impl IntoIterator<Item = FaceRotation>,
+    ) -> anyhow::Result<()> {
+        self.do_moves(&moves.into_iter().collect::<Vec<_>>()).await
+    }
+
+    /// Does a whitespace separated sequence in standard notation including U moves. Unlike
+    /// [`crate::parse_moves_translating_u`], the U and D layers are swapped only when needed and
+    /// left swapped afterwards, which saves moves across calls. Use
+    /// [`Self::reorient_to_neutral`] to swap them back. The tracked state is only updated when
+    /// all moves succeed. Turn counts like `R3` are accepted, see [`normalize_notation`].
+    pub async fn do_moves_with_u(&self, s: &str) -> anyhow::Result<()> {
+        let _executing = self.execute().await?;
+        let mut swapped = self.is_u_d_swapped();
+        let mut moves = vec![];
+        for token in normalize_notation(s)?.split_whitespace() {
+            let (m, on_u) = match token.strip_prefix(['U', 'u']) {
+                Some(suffix) => (FaceRotation::from(format!("d{suffix}")), true),
+                None => (FaceRotation::from(token), false),
+            };
+            if m == FaceRotation::Invalid {
+                continue;
+            }
+            // While swapped, only U can be done, as D. Everything else needs the layers back.
+            if on_u != swapped {
+                moves.extend_from_slice(&U_D_SWAP);
+                swapped = on_u;
+            }
+            moves.push(m);
+        }
+        self.do_moves_exclusive(&moves).await?;
+        self.u_d_swapped.store(swapped, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Returns `true` if [`Self::do_moves_with_u`] left the U and D layers swapped.
+    pub fn is_u_d_swapped(&self) -> bool {
+        self.u_d_swapped.load(Ordering::SeqCst)
+    }
+
+    /// Swaps the U and D layers back if [`Self::do_moves_with_u`] left them swapped, so that
+    /// sequences without U moves apply as expected again.
+    pub async fn reorient_to_neutral(&self) -> anyhow::Result<()> {
+        let _executing = self.execute().await?;
+        if !self.is_u_d_swapped() {
+            return Ok(());
+        }
+        info!("Swapping U and D layers back");
+        self.do_moves_exclusive(&U_D_SWAP).await?;
+        self.u_d_swapped.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Returns the moves written to the robot since connecting, as sent, i.e. after orientation
+    /// remapping and including the moves swapping the U and D layers.
+    pub fn history(&self) -> Vec<FaceRotation> {
+        let history = self.history.lock().unwrap();
+        history.iter().map(|&m| FaceRotation::from(m)).collect()
+    }
+
+    /// Undoes every move in [`Self::history`], last first, to bring the cube back to where it was
+    /// when connecting, and clears the history. The undo is split into writes like any sequence.
+    /// If it fails, the history keeps the moves that were done, including those of the undo.
+    pub async fn undo_all(&self) -> anyhow::Result<()> {
+        let _executing = self.execute().await?;
+        let moves = invert_bytes(&self.history.lock().unwrap());
+        info!("Undoing {} moves", moves.len());
+        let mut sent = 0;
+        for (i, chunk) in split_by(&moves, &self.chunk_lens(&moves)).into_iter().enumerate() {
+            self.wait_while_paused(i).await;
+            self.do_chunk(chunk, sent, CompletionPredicate::Drained).await?;
+            sent += chunk.len();
+        }
+        self.history.lock().unwrap().clear();
+        self.u_d_swapped.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Does a single move with as little overhead as possible. The move is written in one byte
+    /// followed by the terminator nibble, with the rest of the frame filled as for any write,
+    /// since the firmware expects the full frame. With `wait` set to `false` this returns right
+    /// after the write instead of waiting for the move to finish.
+    pub async fn do_move(&self, m: FaceRotation, wait: bool) -> anyhow::Result<()> {
+        info!("Doing move: {m}");
+        let moves = self.encode(&[m]);
+        if moves.is_empty() {
+            anyhow::bail!("Invalid move");
+        }
+        let _executing = self.execute().await?;
+        self.write_moves(&moves).await?;
+        if !wait {
+            return Ok(());
+        }
+        self.sleeper
+            .sleep(Duration::from_millis(
+                (self.timing.move_duration(moves[0]) as f64 * 0.75) as u64,
+            ))
+            .await;
+        let result = self.wait_until_idle().await;
+        self.check_disconnected(result, 1).await
+    }
+
+    /// Does the moves received from `rx` as they arrive until the channel is closed, e.g. to
+    /// follow a timeline. A move is written as soon as the previous write has finished, together
+    /// with any others that arrived in the meantime. A single move then starts after one BLE write
+    /// plus the reads of the dropped move check, typically some tens of milliseconds, but finishing
+    /// is only noticed by polling every [`POLL_INTERVAL_MS`].
+    pub async fn drive_from_channel(&self, mut rx: Receiver<FaceRotation>) -> anyhow::Result<()> {
+        info!("Driving from channel");
+        while let Some(m) = rx.recv().await {
+            let mut moves = vec![m];
+            while moves.len() < self.max_moves_per_write {
+                match rx.try_recv() {
+                    Ok(m) => moves.push(m),
+                    Err(_) => break,
+                }
+            }
+            info!(
+                "Doing moves: {}",
+                moves.iter().map(|m| m.to_string()).collect::<Vec<String>>().join(" ")
+            );
+            let moves = self.encode(&moves);
+            if moves.is_empty() {
+                continue;
+            }
+            let _executing = self.execute().await?;
+            self.write_moves(&moves).await?;
+            let result = self.wait_until_idle().await;
+            self.check_disconnected(result, moves.len()).await?;
+        }
+        info!("Channel closed");
+        Ok(())
+    }
+
+    /// Returns the protocol values of the moves as the robot has to do them, skipping invalid
+    /// ones and merging them first if enabled with [`GanRobotController::with_merge_moves`].
+    fn encode(&self, moves: &[FaceRotation]) -> Vec<u8> {
+        let merged;
+        let moves = if self.merge_moves {
+            merged = cancel_moves(moves);
+            &merged
+        } else {
+            moves
+        };
+        moves
+            .iter()
+            .filter(|m| **m != FaceRotation::Invalid)
+            .map(|&m| u8::from(self.orientation.remap(m)))
+            .collect()
+    }
+
+    /// Pauses a running [`Self::do_moves`] or [`Self::do_moves_with_progress`]. The moves
+    /// already written are finished, but the next chunk is not started until [`Self::resume`].
+    pub fn pause(&self) {
+        self.pause_handle.pause();
+    }
+
+    /// Resumes a sequence paused with [`Self::pause`] from the next chunk.
+    pub fn resume(&self) {
+        self.pause_handle.resume();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pause_handle.is_paused()
+    }
+
+    /// Returns a handle to pause and resume this controller from another task, see
+    /// [`PauseHandle`].
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.pause_handle.clone()
+    }
+
+    async fn wait_while_paused(&self, next_chunk: usize) {
+        self.pause_handle.wait_while_paused(next_chunk).await;
+    }
+
+    /// Like [`Self::do_moves`], but calls `on_move_progress` with the index of each move in
+    /// `moves` as soon as the robot reports it as completed, so that a UI can follow along.
+    /// Invalid moves are skipped and never reported.
+    pub async fn do_moves_with_progress(
+        &self,
+        moves: &[FaceRotation],
+        on_move_progress: impl Fn(usize),
+    ) -> anyhow::Result<()> {
+        info!(
+            "Doing moves: {}",
+            moves.iter().map(|m| m.to_string()).collect::<Vec<String>>().join(" ")
+        );
+        let (indices, moves): (Vec<usize>, Vec<u8>) = moves
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| **m != FaceRotation::Invalid)
+            .map(|(i, &m)| (i, u8::from(self.orientation.remap(m))))
+            .unzip();
+        self.check_sequence_len(moves.len())?;
+        let _executing = self.execute().await?;
+        let lens = self.chunk_lens(&moves);
+        let mut sent = 0;
+        for (i, (indices, chunk)) in split_by(&indices, &lens)
+            .into_iter()
+            .zip(split_by(&moves, &lens))
+            .enumerate()
+        {
+            self.wait_while_paused(i).await;
+            self.call_chunk_hook(&self.before_chunk, chunk);
+            if !self.write_chunk(chunk).await? {
+                continue;
+            }
+            // The remaining count only goes down, so everything before `chunk.len() - remaining`
+            // has been completed.
+            let mut completed = 0;
+            let result = self
+                .wait_until_idle_with(|remaining| {
+                    let done = chunk.len().saturating_sub(remaining as usize);
+                    if done > completed {
+                        indices[completed..done].iter().for_each(|&i| on_move_progress(i));
+                        completed = done;
+                    }
+                })
+                .await;
+            sent += chunk.len();
+            self.check_disconnected(result, sent).await?;
+            self.call_chunk_hook(&self.after_chunk, chunk);
+        }
+        Ok(())
+    }
+
+    /// Like [`Self::do_moves`], but returns how long each valid move took, in order. A move is
+    /// timed from when the previous one completed, or from the write for the first move of a
+    /// write, until the robot reports it as completed. When one status read shows several moves
+    /// completed, the time is split evenly between them. The timings are only as precise as the
+    /// status polling interval.
+    pub async fn do_moves_timed(
+        &self,
+        moves: &[FaceRotation],
+    ) -> anyhow::Result<Vec<(FaceRotation, Duration)>> {
         info!(
             "Doing moves: {}",
             moves.iter().map(|m| m.to_string()).collect::<Vec<String>>().join(" ")
         );
-        let moves = moves
+        let (moves, bytes): (Vec<FaceRotation>, Vec<u8>) = moves
             .iter()
             .filter(|m| **m != FaceRotation::Invalid)
-            .map(u8::from)
-            .collect::<Vec<u8>>();
-        self.do_moves_raw(&moves).await
+            .map(|&m| (m, u8::from(self.orientation.remap(m))))
+            .unzip();
+        self.check_sequence_len(bytes.len())?;
+        let mut timings = Vec::with_capacity(moves.len());
+        let _executing = self.execute().await?;
+        let lens = self.chunk_lens(&bytes);
+        let mut sent = 0;
+        for (i, (moves, chunk)) in split_by(&moves, &lens)
+            .into_iter()
+            .zip(split_by(&bytes, &lens))
+            .enumerate()
+        {
+            self.wait_while_paused(i).await;
+            self.call_chunk_hook(&self.before_chunk, chunk);
+            if !self.write_chunk(chunk).await? {
+                continue;
+            }
+            let mut completed = 0;
+            let mut last = Instant::now();
+            let result = self
+                .wait_until_idle_with(|remaining| {
+                    let done = chunk.len().saturating_sub(remaining as usize);
+                    if done > completed {
+                        let now = Instant::now();
+                        let each = (now - last) / (done - completed) as u32;
+                        timings.extend(moves[completed..done].iter().map(|&m| (m, each)));
+                        completed = done;
+                        last = now;
+                    }
+                })
+                .await;
+            sent += chunk.len();
+            self.check_disconnected(result, sent).await?;
+            self.call_chunk_hook(&self.after_chunk, chunk);
+        }
+        Ok(timings)
+    }
+
+    /// Reads the move buffer capacity from the characteristic set with
+    /// [`GanRobotController::with_capacity_characteristic`]. Returns `None` if there is no such
+    /// characteristic, the read fails, or the firmware reports 0.
+    pub async fn query_max_moves(&self) -> Option<usize> {
+        let characteristic = self.capacity_characteristic.as_ref()?;
+        match self.read(characteristic).await {
+            Ok(value) => {
+                let capacity = value.first().map(|&c| c as usize).filter(|&c| c > 0);
+                info!("Move buffer capacity: {capacity:?}");
+                capacity
+            }
+            Err(e) => {
+                warn!("Failed to read the move buffer capacity: {e}");
+                None
+            }
+        }
+    }
+
+    /// Reads the stickers of the cube from the characteristic set with
+    /// [`GanRobotController::with_cube_state_characteristic`], as 54 bytes with the color of each
+    /// sticker, 9 per face in the order U, R, F, D, L, B. The colors can be any values. Fails if
+    /// the firmware does not report the cube state.
+    pub async fn read_cube_state(&self) -> anyhow::Result<[u8; 54]> {
+        let Some(characteristic) = &self.cube_state_characteristic else {
+            anyhow::bail!("The GAN robot does not report the cube state");
+        };
+        let value = self.read(characteristic).await?;
+        value
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid cube state: {} bytes. Must be 54", value.len()))
+    }
+
+    /// Polls the cube state until every face is a single color, e.g. to wait for a human to
+    /// solve the cube. Fails after `timeout`, or if the firmware does not report the cube state,
+    /// see [`Self::read_cube_state`].
+    ///
+    #[cfg_attr(feature = "test-util", doc = "```")]
+    #[cfg_attr(not(feature = "test-util"), doc = "```ignore")]
+    /// use std::time::Duration;
+    ///
+    /// use lib::{CharPropFlags, FakePeripheral, GanRobotController};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let move_uuid = "0000fff3-0000-1000-8000-00805f9b34fb";
+    /// let status_uuid = "0000fff2-0000-1000-8000-00805f9b34fb";
+    /// let state_uuid = "0000fff5-0000-1000-8000-00805f9b34fb";
+    /// let robot = FakePeripheral::new(move_uuid, status_uuid)?;
+    /// robot.add_characteristic(state_uuid, CharPropFlags::READ)?;
+    /// robot.push_read(robot.status_characteristic(), vec![0]);
+    /// let controller = GanRobotController::try_new("GAN", move_uuid, status_uuid)?
+    ///     .with_cube_state_characteristic(state_uuid)?
+    ///     .from_peripheral(robot.clone())
+    ///     .await?;
+    ///
+    /// let solved = (0..6).flat_map(|face| [face; 9]).collect::<Vec<u8>>();
+    /// let mut scrambled = solved.clone();
+    /// scrambled.swap(0, 9);
+    /// let state = state_uuid.parse()?;
+    /// robot.push_read(state, scrambled);
+    /// assert!(controller.wait_until_solved(Duration::from_millis(300)).await.is_err());
+    ///
+    /// robot.push_read(state, solved);
+    /// controller.wait_until_solved(Duration::from_secs(1)).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn wait_until_solved(&self, timeout: Duration) -> anyhow::Result<()> {
+        let start = Instant::now();
+        loop {
+            let stickers = self.read_cube_state().await?;
+            if stickers.chunks(9).all(|face| face.iter().all(|&c| c == face[0])) {
+                info!("Cube is solved");
+                return Ok(());
+            }
+            if start.elapsed() >= timeout {
+                anyhow::bail!("The cube was not solved within {timeout:?}");
+            }
+            self.sleeper.sleep(Duration::from_millis(POLL_INTERVAL_MS)).await;
+        }
+    }
+
+    async fn read_status(&self) -> anyhow::Result<Vec<u8>> {
+        let Some(status_characteristic) = &self.status_characteristic else {
+            anyhow::bail!("The GAN robot has no status characteristic");
+        };
+        self.read(status_characteristic).await
+    }
+
+    async fn read(&self, characteristic: &Characteristic) -> anyhow::Result<Vec<u8>> {
+        trace!("Reading {}", characteristic.uuid);
+        let value = self.gan_robot.read(characteristic).await?;
+        trace!("Read {}: {}", characteristic.uuid, hex(&value));
+        Ok(value)
+    }
+
+    async fn write(&self, bytes: &[u8]) -> anyhow::Result<()> {
+        trace!("Writing {} ({:?}): {}", self.move_characteristic.uuid, self.write_type, hex(bytes));
+        self.gan_robot
+            .write(&self.move_characteristic, bytes, self.write_type)
+            .await?;
+        Ok(())
+    }
+
+    /// Reads and decodes the status characteristic.
+    pub async fn status(&self) -> anyhow::Result<RobotStatus> {
+        let status = self.read_status().await?;
+        let cube_present = self
+            .cube_presence_flag
+            .and_then(|(index, mask)| status.get(index).map(|b| b & mask != 0));
+        let faults = self
+            .fault_byte
+            .map_or(FaultFlags::empty(), |index| FaultFlags::from_status(&status, index));
+        Ok(RobotStatus {
+            remaining_moves: status.first().copied().unwrap_or(0),
+            cube_present,
+            faults,
+        })
     }
 
     pub async fn get_remaining_moves(&self) -> anyhow::Result<u8> {
-        let status = self.gan_robot.read(&self.status_characteristic).await?;
+        let status = self.read_status().await?;
         let remaining_moves = if status.is_empty() { 0 } else { status[0] };
         info!("Remaining moves: {remaining_moves}");
         Ok(remaining_moves)
     }
 
-    pub async fn do_moves_raw(&self, moves: &[u8]) -> anyhow::Result<()> {
+    /// Like [`Self::get_remaining_moves`], but fails with [`ControllerError::RobotFault`] if the
+    /// status reports a fault, see [`GanRobotController::with_fault_byte`].
+    async fn get_remaining_moves_checking_faults(&self) -> anyhow::Result<u8> {
+        if self.fault_byte.is_none() {
+            return self.get_remaining_moves().await;
+        }
+        let status = self.status().await?;
+        info!("Remaining moves: {}", status.remaining_moves);
+        if !status.faults.is_empty() {
+            return Err(ControllerError::RobotFault(status.faults).into());
+        }
+        Ok(status.remaining_moves)
+    }
+
+    /// Reads the moves still queued in the robot. The status payload starts with the remaining
+    /// move count, optionally followed by the queued moves packed two per byte in the same way
+    /// as they are written. Fails if the firmware does not expose the queue.
+    pub async fn queued_moves(&self) -> anyhow::Result<Vec<FaceRotation>> {
+        let status = self.read_status().await?;
+        let Some((&remaining_moves, queue)) = status.split_first() else {
+            return Ok(vec![]);
+        };
+        if remaining_moves > 0 && queue.is_empty() {
+            anyhow::bail!("The GAN robot does not expose its queued moves");
+        }
+
+        let moves = queue
+            .iter()
+            .flat_map(|b| [b >> 4, b & 0x0f])
+            .take_while(|&m| m != 0x0f)
+            .take(remaining_moves as usize)
+            .map(FaceRotation::from)
+            .collect::<Vec<_>>();
+        info!(
+            "Queued moves: {}",
+            moves.iter().map(|m| m.to_string()).collect::<Vec<String>>().join(" ")
+        );
+        Ok(moves)
+    }
+
+    /// Does moves given as protocol values, returning once `completion` holds. Fails before
+    /// writing anything if a value is above 14, as it would not fit in its nibble.
+    pub async fn do_moves_raw(
+        &self,
+        moves: &[u8],
+        completion: CompletionPredicate,
+    ) -> anyhow::Result<()> {
         info!(
             "Doing moves: {}",
             moves.iter().map(|m| m.to_string()).collect::<Vec<String>>().join(" ")
         );
 
-        if moves.len() > MAX_MOVES_PER_WRITE {
-            anyhow::bail!("Too many moves. Can only do {MAX_MOVES_PER_WRITE} moves at a time");
+        if let Some(m) = moves
+            .iter()
+            .find(|&&m| FaceRotation::from(m) == FaceRotation::Invalid)
+        {
+            anyhow::bail!("Invalid move: {m}. Must be from 0 to 14");
+        }
+        self.check_sequence_len(moves.len())?;
+        let _executing = self.execute().await?;
+        self.do_chunk(moves, 0, completion).await
+    }
+
+    /// Writes a single chunk and waits until `completion` holds. `sent_before` is the number of
+    /// moves of the same sequence written before this chunk.
+    async fn do_chunk(
+        &self,
+        moves: &[u8],
+        sent_before: usize,
+        completion: CompletionPredicate,
+    ) -> anyhow::Result<()> {
+        self.call_chunk_hook(&self.before_chunk, moves);
+        if !self.write_chunk(moves).await? {
+            return Ok(());
         }
+        let written = Instant::now();
 
-        let mut bytes = [0u8; 18];
-        moves.iter().enumerate().for_each(|(i, &m)| {
-            let byte_index = i / 2;
-            bytes[byte_index] += m;
-            if i % 2 == 0 {
-                bytes[byte_index] *= 0x10;
+        // Only the moves that have to be done before returning are worth sleeping for.
+        let must_finish = match completion {
+            CompletionPredicate::BelowThreshold(n) => {
+                moves.len().saturating_sub(n.saturating_sub(1) as usize)
             }
-        });
+            CompletionPredicate::Drained | CompletionPredicate::Timeout(_) => moves.len(),
+        };
+        let sleep_duration = moves[..must_finish]
+            .iter()
+            .map(|&m| self.timing.move_duration(m))
+            .sum::<usize>();
+        let mut sleep_duration = Duration::from_millis((sleep_duration as f64 * 0.75) as u64);
+        if let CompletionPredicate::Timeout(timeout) = completion {
+            sleep_duration = sleep_duration.min(timeout);
+        }
+        self.sleeper.sleep(sleep_duration).await;
 
-        if moves.len() % 2 == 1 {
-            bytes[(moves.len() / 2).saturating_sub(1)] += 0x0f;
+        let result = match completion {
+            CompletionPredicate::Drained => self.wait_until_idle().await,
+            CompletionPredicate::BelowThreshold(n) => self.wait_until_below_with(n, |_| {}).await,
+            CompletionPredicate::Timeout(timeout) => {
+                let timeout = timeout.saturating_sub(written.elapsed());
+                let idle = std::pin::pin!(self.wait_until_idle());
+                let timed_out = std::pin::pin!(self.sleeper.sleep(timeout));
+                match futures::future::select(idle, timed_out).await {
+                    Either::Left((result, _)) => result,
+                    Either::Right(_) => {
+                        info!("Stopped waiting for the GAN robot to finish after {timeout:?}");
+                        Ok(())
+                    }
+                }
+            }
+        };
+        self.check_disconnected(result, sent_before + moves.len()).await?;
+        self.call_chunk_hook(&self.after_chunk, moves);
+        Ok(())
+    }
+
+    /// Returns the lengths of the chunks `moves` is split into, see [`BatchBy`].
+    fn chunk_lens(&self, moves: &[u8]) -> Vec<usize> {
+        let mut lens = vec![];
+        let mut len = 0;
+        let mut ms = 0;
+        for &m in moves {
+            let full = match self.batch_by {
+                BatchBy::MoveCount(n) => len >= n,
+                BatchBy::Duration(d) => ms + self.timing.move_duration(m) > d.as_millis() as usize,
+            };
+            if len > 0 && (full || len >= self.max_moves_per_write) {
+                lens.push(len);
+                len = 0;
+                ms = 0;
+            }
+            len += 1;
+            ms += self.timing.move_duration(m);
         }
+        if len > 0 {
+            lens.push(len);
+        }
+        lens
+    }
 
-        for i in bytes.iter_mut().skip(moves.len()) {
-            *i = 0xff;
+    fn call_chunk_hook(&self, hook: &Option<Arc<OnChunk>>, moves: &[u8]) {
+        if let Some(hook) = hook {
+            hook(&moves.iter().map(|&m| FaceRotation::from(m)).collect::<Vec<_>>());
         }
+    }
 
-        let sleep_duration = moves.iter().map(|&m| move_duration(m)).sum::<usize>();
+    /// Writes a chunk of a sequence, handling BLE errors according to the [`ErrorPolicy`].
+    /// Returns `false` if the chunk was skipped.
+    async fn write_chunk(&self, moves: &[u8]) -> anyhow::Result<bool> {
+        let (retries, skip) = match self.error_policy {
+            ErrorPolicy::Abort => (0, false),
+            ErrorPolicy::RetryThenAbort(n) => (n, false),
+            ErrorPolicy::RetryThenSkip(n) => (n, true),
+        };
+        let mut attempt = 0;
+        loop {
+            match self.write_moves(moves).await {
+                Ok(()) => return Ok(true),
+                // Other errors, such as a failed dropped move check, are not transient, and a
+                // retry could do moves twice.
+                Err(e) if e.downcast_ref::<btleplug::Error>().is_none() => return Err(e),
+                Err(e) if attempt < retries => {
+                    attempt += 1;
+                    warn!(
+                        "Write of {} moves failed, retrying ({attempt}/{retries}): {e}",
+                        moves.len()
+                    );
+                }
+                Err(e) if skip => {
+                    warn!("Skipping {} moves after the write failed: {e}", moves.len());
+                    return Ok(false);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
 
-        self.gan_robot
-            .write(&self.move_characteristic, &bytes, WriteType::WithoutResponse)
-            .await?;
-        sleep(Duration::from_millis((sleep_duration as f64 * 0.75) as u64)).await;
+    /// Turns an error while waiting for moves into
+    /// [`ControllerError::DisconnectedDuringExecution`] if the robot is no longer connected.
+    async fn check_disconnected(
+        &self,
+        result: anyhow::Result<()>,
+        moves_sent: usize,
+    ) -> anyhow::Result<()> {
+        match result {
+            Err(_) if !self.gan_robot.is_connected().await.unwrap_or(false) => {
+                Err(ControllerError::DisconnectedDuringExecution { moves_sent }.into())
+            }
+            result => result,
+        }
+    }
+
+    async fn write_moves(&self, moves: &[u8]) -> anyhow::Result<()> {
+        if moves.len() > self.max_moves_per_write {
+            anyhow::bail!(
+                "Too many moves. Can only do {} moves at a time",
+                self.max_moves_per_write
+            );
+        }
+        if self.require_cube_present
+            && self.status_characteristic.is_some()
+            && self.status().await?.cube_present == Some(false)
+        {
+            return Err(ControllerError::CubeNotPresent.into());
+        }
+
+        // The moves are packed two per byte, high nibble first, and terminated by a 0xf nibble.
+        // The rest of the frame is the fill byte.
+        let mut bytes = [self.fill_byte; FRAME_LEN];
+        let packed = pack_frame(moves);
+        bytes[..packed.len()].copy_from_slice(&packed);
+
+        if let Some(on_move_sent) = &self.on_move_sent {
+            moves.iter().for_each(|&m| on_move_sent(FaceRotation::from(m)));
+        }
+
+        if self.status_characteristic.is_none() {
+            self.write(&bytes).await?;
+            self.history.lock().unwrap().extend_from_slice(moves);
+            let duration = Duration::from_millis(
+                moves.iter().map(|&m| self.timing.move_duration(m)).sum::<usize>() as u64,
+            );
+            let mut busy_until = self.busy_until.lock().unwrap();
+            *busy_until = (*busy_until).max(Instant::now()) + duration;
+            return Ok(());
+        }
+
+        // Writes without response are not confirmed, so compare the remaining count before and
+        // after to notice a dropped write. Only writes that arrived not at all are retried, as
+        // retrying a partial one would do some moves twice.
+        let before = self.get_remaining_moves().await?;
+        for attempt in 0..=self.write_retries {
+            self.write(&bytes).await?;
+            if !self.status_read_delay.is_zero() {
+                self.sleeper.sleep(self.status_read_delay).await;
+            }
+            let after = self.get_remaining_moves().await?;
+            let received = (after.saturating_sub(before) as usize).min(moves.len());
+            if received == 0 && attempt < self.write_retries {
+                warn!("Write of {} moves was dropped, retrying", moves.len());
+                continue;
+            }
+            self.history.lock().unwrap().extend_from_slice(moves);
+            return self.check_dropped_moves(moves.len(), received, before, after);
+        }
+        Ok(())
+    }
 
-        while self.get_remaining_moves().await? > 0 {
-            sleep(Duration::from_millis(100)).await;
+    fn check_dropped_moves(
+        &self,
+        sent: usize,
+        received: usize,
+        before: u8,
+        after: u8,
+    ) -> anyhow::Result<()> {
+        // The robot may already have finished a move by the time the count is read.
+        if sent - received <= DROPPED_MOVE_TOLERANCE {
+            return Ok(());
+        }
+        let message = format!(
+            "{} of {sent} moves were possibly dropped: remaining moves went from {before} to {after}",
+            sent - received
+        );
+        match self.dropped_move_check {
+            DroppedMoveCheck::Off => {}
+            DroppedMoveCheck::Warn => warn!("{message}"),
+            DroppedMoveCheck::Error => anyhow::bail!("{message}"),
+        }
+        Ok(())
+    }
+
+    fn check_sequence_len(&self, len: usize) -> anyhow::Result<()> {
+        if let Some(max_sequence_len) = self.max_sequence_len {
+            if len > max_sequence_len {
+                anyhow::bail!(
+                    "Too many moves: {len}. The sequence length is capped at {max_sequence_len} moves"
+                );
+            }
         }
         Ok(())
     }
 
+    /// Waits until the robot reports no remaining moves. Fails if the remaining count does not
+    /// change for [`STUCK_TIMEOUT_MS`], as the robot is then most likely stuck. Without a status
+    /// characteristic, this waits until the moves written are estimated to be done instead.
+    pub async fn wait_until_idle(&self) -> anyhow::Result<()> {
+        self.wait_until_idle_with(|_| {}).await
+    }
+
+    /// Like [`Self::wait_until_idle`], but calls `on_remaining` with every remaining count read.
+    async fn wait_until_idle_with(&self, on_remaining: impl FnMut(u8)) -> anyhow::Result<()> {
+        self.wait_until_below_with(1, on_remaining).await
+    }
+
+    /// Like [`Self::wait_until_idle_with`], but returns once fewer than `threshold` moves remain.
+    async fn wait_until_below_with(
+        &self,
+        threshold: u8,
+        mut on_remaining: impl FnMut(u8),
+    ) -> anyhow::Result<()> {
+        // No count is below 0, so that would wait forever.
+        let threshold = threshold.max(1);
+        if self.status_characteristic.is_none() {
+            let busy_until = *self.busy_until.lock().unwrap();
+            self.sleeper
+                .sleep(busy_until.saturating_duration_since(Instant::now()))
+                .await;
+            on_remaining(0);
+            return Ok(());
+        }
+        let mut remaining_moves = self.get_remaining_moves_checking_faults().await?;
+        on_remaining(remaining_moves);
+        let mut last_progress = Instant::now();
+        while remaining_moves >= threshold {
+            if last_progress.elapsed() > Duration::from_millis(STUCK_TIMEOUT_MS) {
+                anyhow::bail!(
+                    "GAN robot seems stuck with {remaining_moves} remaining moves for {STUCK_TIMEOUT_MS} ms"
+                );
+            }
+            self.sleeper.sleep(Duration::from_millis(POLL_INTERVAL_MS)).await;
+            let current = self.get_remaining_moves_checking_faults().await?;
+            on_remaining(current);
+            if current != remaining_moves {
+                remaining_moves = current;
+                last_progress = Instant::now();
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the last signal strength of the robot in dBm as reported by the platform.
+    pub async fn rssi(&self) -> anyhow::Result<i16> {
+        self.gan_robot
+            .properties()
+            .await?
+            .and_then(|p| p.rssi)
+            .ok_or_else(|| anyhow::anyhow!("Signal strength is not available"))
+    }
+
+    /// Returns `true` if `rssi` is below the threshold set with
+    /// [`GanRobotController::with_low_rssi_threshold`], at which writes are likely to be dropped.
+    pub fn is_weak_signal(&self, rssi: i16) -> bool {
+        rssi < self.low_rssi_threshold
+    }
+
+    /// Returns the signal strength every `interval`, see [`Self::rssi`].
+    pub fn rssi_stream(&self, interval: Duration) -> impl Stream<Item = anyhow::Result<i16>> + '_ {
+        futures::stream::unfold(true, move |first| async move {
+            if !first {
+                self.sleeper.sleep(interval).await;
+            }
+            Some((self.rssi().await, false))
+        })
+    }
+
+    /// Returns the services of the robot with their characteristics, e.g. to find the UUIDs of
+    /// another model.
+    pub async fn describe_gatt(&self) -> Vec<ServiceInfo> {
+        describe_services(&self.gan_robot)
+    }
+
+    /// Subscribes to the notifications of any characteristic of the robot, e.g. to find out what
+    /// the other characteristics do. Fails if the characteristic does not support notifications.
+    pub async fn subscribe(&self, uuid: Uuid) -> anyhow::Result<Subscription<P>> {
+        let characteristic = self
+            .gan_robot
+            .characteristics()
+            .into_iter()
+            .find(|c| c.uuid == uuid)
+            .ok_or_else(|| anyhow::anyhow!("Characteristic {uuid} not found"))?;
+        if !characteristic
+            .properties
+            .intersects(CharPropFlags::NOTIFY | CharPropFlags::INDICATE)
+        {
+            anyhow::bail!("Characteristic {uuid} does not support notifications");
+        }
+        info!("Subscribing to {uuid}");
+        Subscription::new(&self.gan_robot, characteristic).await
+    }
+
+    /// Returns what the robot told about itself when connecting.
+    pub fn device_info(&self) -> &DeviceInfo {
+        &self.device_info
+    }
+
+    /// Returns the peripheral of the robot, e.g. to connect to it again later with
+    /// [`GanRobotController::from_peripheral`].
+    pub fn peripheral(&self) -> P {
+        self.gan_robot.clone()
+    }
+
+    /// Returns `true` once the controller has disconnected, or is about to, after the timeout
+    /// set with [`GanRobotController::with_inactivity_timeout`].
+    pub fn is_disconnected_for_inactivity(&self) -> bool {
+        self.watchdog.as_ref().is_some_and(Watchdog::has_expired)
+    }
+
+    /// Waits until the controller has disconnected after the timeout set with
+    /// [`GanRobotController::with_inactivity_timeout`], e.g. to stop serving requests. Never
+    /// returns without a timeout.
+    pub async fn disconnected_for_inactivity(&self) {
+        match &self.watchdog {
+            Some(watchdog) => watchdog.disconnected().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Locks [`Connected::executing`] for doing moves, which also holds off the inactivity
+    /// timeout. Fails if the controller has already disconnected for inactivity.
+    async fn execute(&self) -> anyhow::Result<Executing<'_>> {
+        let lock = self.executing.lock().await;
+        let activity = self.watchdog.as_ref().map(Watchdog::start_activity).transpose()?;
+        Ok(Executing { _lock: lock, _activity: activity })
+    }
+
+    /// Sets the turning speed of the robot. The known firmware exposes no speed control, only
+    /// the move and status characteristics, so no `level` is supported and this always fails
+    /// without writing anything.
+    pub async fn set_speed(&self, level: u8) -> anyhow::Result<()> {
+        anyhow::bail!("Setting the speed to {level} is not supported by the GAN robot firmware")
+    }
+
+    /// Disconnects from the robot. Does nothing if it was already disconnected for inactivity,
+    /// see [`GanRobotController::with_inactivity_timeout`].
     pub async fn disconnect(&self) -> anyhow::Result<()> {
+        if self.is_disconnected_for_inactivity() {
+            return Ok(());
+        }
         info!("Disconnecting from GAN robot");
+        trace!("Disconnecting");
         self.gan_robot.disconnect().await?;
         Ok(())
     }
+
+    /// Like [`Self::disconnect`], but waits for in-flight moves to finish first so the robot is
+    /// not left mid-turn. Disconnects anyway if the robot seems stuck.
+    pub async fn disconnect_when_idle(&self) -> anyhow::Result<()> {
+        if let Err(e) = self.wait_until_idle().await {
+            warn!("Disconnecting without waiting for the robot to be idle: {e}");
+        }
+        self.disconnect().await
+    }
+
+    /// Ends a session: waits for the moves in progress, also those of concurrent calls, swaps the
+    /// U and D layers back if [`Self::do_moves_with_u`] left them swapped, and disconnects.
+    /// Disconnects anyway if the robot seems stuck or swapping back fails.
+    pub async fn shutdown(&self) -> anyhow::Result<()> {
+        if self.is_disconnected_for_inactivity() {
+            return Ok(());
+        }
+        let _executing = self.executing.lock().await;
+        let result = async {
+            self.wait_until_idle().await?;
+            if self.is_u_d_swapped() {
+                info!("Swapping U and D layers back");
+                self.do_moves_exclusive(&U_D_SWAP).await?;
+                self.u_d_swapped.store(false, Ordering::SeqCst);
+            }
+            anyhow::Ok(())
+        }
+        .await;
+        if let Err(e) = result {
+            warn!("Disconnecting without finishing the session cleanly: {e}");
+        }
+        self.disconnect().await
+    }
+}
+
+/// Turns a missing Bluetooth permission, which btleplug only reports as "Permission denied",
+/// into an error that says how to grant it. Other errors are kept as they are.
+fn bluetooth_error(e: btleplug::Error) -> anyhow::Error {
+    match e {
+        btleplug::Error::PermissionDenied if cfg!(target_os = "macos") => anyhow::anyhow!(
+            "Bluetooth permission denied. Allow your terminal app in System Settings > Privacy & Security > Bluetooth and restart it"
+        ),
+        btleplug::Error::PermissionDenied => anyhow::anyhow!(
+            "Bluetooth permission denied. Make sure your user may use Bluetooth, e.g. is in the bluetooth group"
+        ),
+        e => e.into(),
+    }
+}
+
+fn describe_services(peripheral: &impl api::Peripheral) -> Vec<ServiceInfo> {
+    peripheral
+        .services()
+        .into_iter()
+        .map(|s| ServiceInfo {
+            uuid: s.uuid,
+            characteristics: s
+                .characteristics
+                .into_iter()
+                .map(|c| CharacteristicInfo { uuid: c.uuid, properties: c.properties })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Splits `items` into consecutive slices of the given lengths.
+fn split_by<'a, T>(mut items: &'a [T], lens: &[usize]) -> Vec<&'a [T]> {
+    lens.iter()
+        .map(|&len| {
+            let (chunk, rest) = items.split_at(len);
+            items = rest;
+            chunk
+        })
+        .collect()
+}
+
+/// Estimates how long the robot takes for the given moves with the default [`TimingConfig`], not
+/// counting BLE latency.
+pub fn estimated_duration(moves: &[FaceRotation]) -> Duration {
+    TimingConfig::default().estimated_duration(moves)
+}
+
+/// Returns how many writes `num_moves` moves take when split into chunks of at most
+/// `chunk_size`, as with [`GanRobotController::with_max_moves_per_write`]. Batching by
+/// [`BatchBy::Duration`] may split into more writes. Panics if `chunk_size` is zero.
+///
+/// ```
+/// use lib::write_count;
+///
+/// assert_eq!(write_count(0, 36), 0);
+/// assert_eq!(write_count(1, 36), 1);
+/// assert_eq!(write_count(35, 36), 1);
+/// assert_eq!(write_count(36, 36), 1);
+/// assert_eq!(write_count(37, 36), 2);
+/// assert_eq!(write_count(72, 36), 2);
+/// assert_eq!(write_count(5, 1), 5);
+/// ```
+pub fn write_count(num_moves: usize, chunk_size: usize) -> usize {
+    num_moves.div_ceil(chunk_size)
+}
+
+/// Does `moves` on all `controllers` at the same time and waits for all of them to finish, e.g.
+/// to scramble two robots identically for a head-to-head demo. Connect to each robot with its
+/// own name, see [`GanRobotController::try_new`].
+///
+/// Sequences longer than the smallest maximum moves per write of the controllers are split into
+/// writes of that size, and each write starts on all robots together once all of them are done
+/// with the previous one. So the robots never drift apart by more than one write, at the cost of
+/// the faster robots waiting for the slowest. Within a write they are still apart by the
+/// difference in BLE latency, typically some tens of milliseconds, and by how fast their motors
+/// are. Moves are only merged within a write, see [`GanRobotController::with_merge_moves`].
+/// If one robot fails, the others still finish the current write, nothing more is written and
+/// the first error is returned.
+///
+#[cfg_attr(feature = "test-util", doc = "```")]
+#[cfg_attr(not(feature = "test-util"), doc = "```ignore")]
+/// use lib::{broadcast_moves, decode_frame, moves, DroppedMoveCheck, FakePeripheral, GanRobotController};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> anyhow::Result<()> {
+/// let move_uuid = "0000fff3-0000-1000-8000-00805f9b34fb";
+/// let status_uuid = "0000fff2-0000-1000-8000-00805f9b34fb";
+/// let robots = [(); 2].map(|()| FakePeripheral::new(move_uuid, status_uuid).unwrap());
+/// let mut controllers = vec![];
+/// for robot in &robots {
+///     robot.push_read(robot.status_characteristic(), vec![0]);
+///     let controller = GanRobotController::try_new("GAN", move_uuid, status_uuid)?
+///         .with_dropped_move_check(DroppedMoveCheck::Off)
+///         .from_peripheral(robot.clone())
+///         .await?;
+///     controllers.push(controller);
+/// }
+///
+/// broadcast_moves(&controllers.iter().collect::<Vec<_>>(), &moves!("R F'")).await?;
+/// for robot in &robots {
+///     let writes = robot.writes(robot.move_characteristic());
+///     assert_eq!(writes.len(), 1);
+///     assert_eq!(decode_frame(&writes[0])?, [0, 5]);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn broadcast_moves<P: api::Peripheral + 'static>(
+    controllers: &[&GanRobotController<Connected<P>>],
+    moves: &[FaceRotation],
+) -> anyhow::Result<()> {
+    let Some(chunk_len) = controllers.iter().map(|c| c.max_moves_per_write).min() else {
+        return Ok(());
+    };
+    for chunk in moves.chunks(chunk_len) {
+        // The futures are polled in turn, so the writes go out right after one another.
+        let results =
+            futures::future::join_all(controllers.iter().map(|c| c.do_moves(chunk))).await;
+        for (i, result) in results.into_iter().enumerate() {
+            result.map_err(|e| {
+                e.context(format!("Robot {} of {} failed", i + 1, controllers.len()))
+            })?;
+        }
+    }
+    Ok(())
 }
 
 fn is_double_turn_move(m: u8) -> bool {
     m % 3 == 1
 }
 
-fn move_duration(m: u8) -> usize {
-    if is_double_turn_move(m) {
-        DOUBLE_TURN_DURATION_MS
-    } else {
-        QUANTUM_TURN_DURATION_MS
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode_frame, moves, FakePeripheral, RecordingSleeper};
+
+    const MOVE_UUID: &str = "0000fff3-0000-1000-8000-00805f9b34fb";
+    const STATUS_UUID: &str = "0000fff2-0000-1000-8000-00805f9b34fb";
+
+    /// Returns a fake that reports no remaining moves until scripted otherwise.
+    fn fake_robot() -> FakePeripheral {
+        let robot = FakePeripheral::new(MOVE_UUID, STATUS_UUID).unwrap();
+        robot.push_read(robot.status_characteristic(), vec![0]);
+        robot
+    }
+
+    /// Returns a builder that does not sleep and does not check for dropped moves, which the
+    /// fake does not simulate.
+    fn builder(sleeper: &RecordingSleeper) -> GanRobotController<Uninitialized> {
+        GanRobotController::try_new("GAN", MOVE_UUID, STATUS_UUID)
+            .unwrap()
+            .with_dropped_move_check(DroppedMoveCheck::Off)
+            .with_sleeper(sleeper.clone())
+    }
+
+    /// Returns the protocol values of every frame written to the fake.
+    fn written_moves(robot: &FakePeripheral) -> Vec<Vec<u8>> {
+        let writes = robot.writes(robot.move_characteristic());
+        writes.iter().map(|frame| decode_frame(frame).unwrap()).collect()
+    }
+
+    fn script_remaining(robot: &FakePeripheral, remaining: &[u8]) {
+        for &r in remaining {
+            robot.push_read(robot.status_characteristic(), vec![r]);
+        }
+    }
+
+    #[tokio::test]
+    async fn sleeper_records_the_waits_of_a_chunk() {
+        let robot = fake_robot();
+        let sleeper = RecordingSleeper::new();
+        let controller = builder(&sleeper).from_peripheral(robot.clone()).await.unwrap();
+
+        // The 0 queued by `fake_robot` is the count read before the write.
+        script_remaining(&robot, &[3, 1, 0]);
+        controller.do_moves(&moves!("R F2 D'")).await.unwrap();
+        assert_eq!(written_moves(&robot), [[0, 4, 8]]);
+        let ms = Duration::from_millis;
+        // The status read delay, then 3/4 of the 550 ms estimate, then a poll until done.
+        assert_eq!(sleeper.durations(), [ms(30), ms(412), ms(100)]);
+    }
+
+    #[tokio::test]
+    async fn connecting_fails_without_the_write_flag() {
+        let robot = fake_robot();
+        let read_only = "0000fff9-0000-1000-8000-00805f9b34fb";
+        robot.add_characteristic(read_only, CharPropFlags::READ).unwrap();
+        let e = GanRobotController::try_new("GAN", read_only, STATUS_UUID)
+            .unwrap()
+            .from_peripheral(robot.clone())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "Characteristic {read_only} lacks the required properties: CharPropFlags(WRITE_WITHOUT_RESPONSE)"
+            )
+        );
+
+        // The fake only allows writes without response.
+        let e = builder(&RecordingSleeper::new())
+            .with_write_type(WriteType::WithResponse)
+            .from_peripheral(robot)
+            .await
+            .err()
+            .unwrap();
+        assert!(e.to_string().ends_with("properties: CharPropFlags(WRITE)"), "{e}");
+    }
+
+    #[tokio::test]
+    async fn do_moves_raw_rejects_values_above_14() {
+        let robot = fake_robot();
+        let controller = builder(&RecordingSleeper::new())
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+
+        for invalid in [15, 16, 255] {
+            let e = controller
+                .do_moves_raw(&[0, invalid], CompletionPredicate::Drained)
+                .await
+                .unwrap_err();
+            assert_eq!(e.to_string(), format!("Invalid move: {invalid}. Must be from 0 to 14"));
+        }
+        assert!(robot.writes(robot.move_characteristic()).is_empty());
+
+        controller
+            .do_moves_raw(&[14, 0], CompletionPredicate::Drained)
+            .await
+            .unwrap();
+        assert_eq!(written_moves(&robot), [[14, 0]]);
+    }
+
+    #[tokio::test]
+    async fn disconnecting_while_polling_reports_the_moves_sent() {
+        let robot = fake_robot();
+        let sleeper = RecordingSleeper::new();
+        let controller = builder(&sleeper).from_peripheral(robot.clone()).await.unwrap();
+
+        // The robot never gets past 2 remaining moves, and drops out during the polls.
+        script_remaining(&robot, &[3, 2]);
+        let moves = moves!("R F2 D'");
+        let (result, ()) = tokio::join!(controller.do_moves(&moves), async {
+            while sleeper.durations().len() < 4 {
+                tokio::task::yield_now().await;
+            }
+            robot.set_connected(false);
+        });
+        let e = result.unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ControllerError>(),
+            Some(&ControllerError::DisconnectedDuringExecution { moves_sent: 3 })
+        );
+    }
+
+    #[tokio::test]
+    async fn subscriptions_get_the_notifications_of_their_characteristic() {
+        let robot = fake_robot();
+        let controller = builder(&RecordingSleeper::new())
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+
+        let mut subscription = controller.subscribe(robot.status_characteristic()).await.unwrap();
+        robot.notify(robot.status_characteristic(), vec![7]);
+        assert_eq!(subscription.next().await, Some(vec![7]));
+        subscription.unsubscribe().await.unwrap();
+
+        // The move characteristic does not notify.
+        assert!(controller.subscribe(robot.move_characteristic()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn describe_gatt_lists_the_characteristics_with_their_properties() {
+        let robot = fake_robot();
+        let controller = builder(&RecordingSleeper::new())
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            controller.describe_gatt().await,
+            [ServiceInfo {
+                uuid: Uuid::parse_str(GAN_ROBOT_SERVICE).unwrap(),
+                characteristics: vec![
+                    CharacteristicInfo {
+                        uuid: robot.status_characteristic(),
+                        properties: CharPropFlags::READ | CharPropFlags::NOTIFY,
+                    },
+                    CharacteristicInfo {
+                        uuid: robot.move_characteristic(),
+                        properties: CharPropFlags::WRITE_WITHOUT_RESPONSE,
+                    },
+                ],
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_sequences_do_not_interleave() {
+        let robot = fake_robot();
+        let controller = builder(&RecordingSleeper::new())
+            .with_batch_by(BatchBy::MoveCount(1))
+            .unwrap()
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+
+        let (first, second) = (moves!("R F D"), moves!("L B R"));
+        let (a, b) = tokio::join!(controller.do_moves(&first), controller.do_moves(&second));
+        a.unwrap();
+        b.unwrap();
+        let written = written_moves(&robot).concat();
+        assert!(written == [0, 3, 6, 9, 12, 0] || written == [9, 12, 0, 0, 3, 6], "{written:?}");
+    }
+
+    #[tokio::test]
+    async fn shutdown_waits_for_the_moves_then_disconnects() {
+        let robot = FakePeripheral::new(MOVE_UUID, STATUS_UUID).unwrap();
+        let sleeper = RecordingSleeper::new();
+        let controller = builder(&sleeper).from_peripheral(robot.clone()).await.unwrap();
+
+        // Moves of an earlier session are still in progress.
+        script_remaining(&robot, &[2, 1, 0]);
+        controller.shutdown().await.unwrap();
+        let ms = Duration::from_millis;
+        assert_eq!(sleeper.durations(), [ms(100), ms(100)]);
+        assert!(!robot.is_connected().await.unwrap());
+        assert!(robot.writes(robot.move_characteristic()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn undo_all_returns_the_cube_to_the_start_of_the_session() {
+        let robot = fake_robot();
+        let controller = builder(&RecordingSleeper::new())
+            .with_max_moves_per_write(2)
+            .unwrap()
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+
+        controller.do_moves(&moves!("R F2 D'")).await.unwrap();
+        controller.do_moves(&moves!("L B'")).await.unwrap();
+        controller.undo_all().await.unwrap();
+        assert!(controller.history().is_empty());
+
+        let written = written_moves(&robot);
+        // The undo respects the chunk limit like any sequence.
+        assert_eq!(written[3..], [vec![12, 11], vec![6, 4], vec![2]]);
+        let mut state = CubeState::solved();
+        let moves = written
+            .concat()
+            .into_iter()
+            .map(FaceRotation::from)
+            .collect::<Vec<_>>();
+        state.apply_all(&moves);
+        assert!(state.is_solved());
+    }
+
+    #[tokio::test]
+    async fn chunk_hooks_fire_around_every_chunk() {
+        let robot = fake_robot();
+        let log = Arc::new(std::sync::Mutex::new(vec![]));
+        let hook = |when: &'static str| {
+            let log = log.clone();
+            move |moves: &[FaceRotation]| log.lock().unwrap().push((when, moves.to_vec()))
+        };
+        let controller = builder(&RecordingSleeper::new())
+            .with_batch_by(BatchBy::MoveCount(2))
+            .unwrap()
+            .with_before_chunk(hook("before"))
+            .with_after_chunk(hook("after"))
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+
+        controller.do_moves(&moves!("R F2 D'")).await.unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                ("before", moves!("R F2")),
+                ("after", moves!("R F2")),
+                ("before", moves!("D'")),
+                ("after", moves!("D'")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn batching_by_move_count_splits_after_that_many_moves() {
+        let robot = fake_robot();
+        let controller = builder(&RecordingSleeper::new())
+            .with_batch_by(BatchBy::MoveCount(2))
+            .unwrap()
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+
+        controller.do_moves(&moves!("R F2 D' L B")).await.unwrap();
+        assert_eq!(written_moves(&robot), [vec![0, 4], vec![8, 9], vec![12]]);
+    }
+
+    #[tokio::test]
+    async fn batching_by_duration_keeps_each_write_under_the_limit() {
+        let robot = fake_robot();
+        let controller = builder(&RecordingSleeper::new())
+            .with_batch_by(BatchBy::Duration(Duration::from_millis(400)))
+            .unwrap()
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+
+        // 150 + 250 ms fit, another quarter turn does not, and neither does a double turn after
+        // 300 ms of quarter turns.
+        controller.do_moves(&moves!("R F2 D' L B2")).await.unwrap();
+        assert_eq!(written_moves(&robot), [vec![0, 4], vec![8, 9], vec![13]]);
+
+        let robot = fake_robot();
+        let controller = builder(&RecordingSleeper::new())
+            .with_batch_by(BatchBy::Duration(Duration::from_millis(100)))
+            .unwrap()
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+        // Moves longer than the limit are still sent, one per write.
+        controller.do_moves(&moves!("R F2")).await.unwrap();
+        assert_eq!(written_moves(&robot), [vec![0], vec![4]]);
+    }
+
+    #[tokio::test]
+    async fn settle_at_end_waits_once_for_a_single_chunk() {
+        let robot = fake_robot();
+        let sleeper = RecordingSleeper::new();
+        let controller = builder(&sleeper)
+            .with_settle_at_end(true)
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+
+        script_remaining(&robot, &[3, 0]);
+        controller.do_moves(&moves!("R F2 D'")).await.unwrap();
+        let ms = Duration::from_millis;
+        // The status read delay and the full 550 ms estimate, without polling.
+        assert_eq!(sleeper.durations(), [ms(30), ms(550)]);
+
+        // An empty sequence is not written and not waited for.
+        controller.do_moves(&[]).await.unwrap();
+        assert_eq!(written_moves(&robot).len(), 1);
+        assert_eq!(sleeper.durations().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn a_reported_capacity_below_36_limits_the_writes() {
+        let robot = fake_robot();
+        let capacity = "0000fff4-0000-1000-8000-00805f9b34fb";
+        robot.add_characteristic(capacity, CharPropFlags::READ).unwrap();
+        robot.push_read(Uuid::parse_str(capacity).unwrap(), vec![10]);
+        let controller = builder(&RecordingSleeper::new())
+            .with_capacity_characteristic(capacity)
+            .unwrap()
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(controller.query_max_moves().await, Some(10));
+        controller.do_moves(&[FaceRotation::R; 12]).await.unwrap();
+        assert_eq!(written_moves(&robot), [vec![0; 10], vec![0; 2]]);
+    }
+
+    #[tokio::test]
+    async fn a_count_that_does_not_grow_is_retried_and_reported() {
+        let robot = fake_robot();
+        let controller = builder(&RecordingSleeper::new())
+            .with_write_retries(1)
+            .with_dropped_move_check(DroppedMoveCheck::Error)
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+
+        // The remaining count stays at 0, as if the writes never arrived.
+        let e = controller.do_moves(&moves!("R F2 D'")).await.unwrap_err();
+        assert_eq!(written_moves(&robot), [[0, 4, 8], [0, 4, 8]]);
+        assert_eq!(
+            e.to_string(),
+            "3 of 3 moves were possibly dropped: remaining moves went from 0 to 0"
+        );
+    }
+
+    #[tokio::test]
+    async fn u_d_layers_stay_swapped_until_reoriented() {
+        let robot = fake_robot();
+        let controller = builder(&RecordingSleeper::new())
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+        let swap = U_D_SWAP.map(u8::from);
+        let written = || written_moves(&robot).concat();
+
+        // A U move swaps the layers and leaves them swapped.
+        controller.do_moves_with_u("R U").await.unwrap();
+        assert!(controller.is_u_d_swapped());
+        assert_eq!(written(), [&[0][..], &swap, &[6]].concat());
+
+        // Further U moves need no swap, other faces swap back.
+        controller.do_moves_with_u("U' R").await.unwrap();
+        assert!(!controller.is_u_d_swapped());
+        assert_eq!(written()[8..], [&[8][..], &swap, &[0]].concat());
+
+        controller.do_moves_with_u("U").await.unwrap();
+        controller.reorient_to_neutral().await.unwrap();
+        assert!(!controller.is_u_d_swapped());
+        assert_eq!(written()[16..], [&swap[..], &[6], &swap].concat());
+
+        // Reorienting when neutral does nothing.
+        controller.reorient_to_neutral().await.unwrap();
+        assert_eq!(written().len(), 29);
+    }
+
+    #[tokio::test]
+    async fn sequences_above_the_cap_are_rejected_before_writing() {
+        let robot = fake_robot();
+        let controller = builder(&RecordingSleeper::new())
+            .with_max_sequence_len(Some(2))
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+
+        let e = controller.do_moves(&moves!("R F D")).await.unwrap_err();
+        assert_eq!(e.to_string(), "Too many moves: 3. The sequence length is capped at 2 moves");
+        assert!(controller
+            .do_moves_raw(&[0, 3, 6], CompletionPredicate::Drained)
+            .await
+            .is_err());
+        assert!(robot.writes(robot.move_characteristic()).is_empty());
+
+        controller.do_moves(&moves!("R F")).await.unwrap();
+        assert_eq!(written_moves(&robot), [[0, 3]]);
+    }
+
+    #[test]
+    fn permission_errors_say_how_to_grant_access() {
+        let e = bluetooth_error(btleplug::Error::PermissionDenied).to_string();
+        assert!(e.starts_with("Bluetooth permission denied. "), "{e}");
+        let hint = if cfg!(target_os = "macos") { "System Settings" } else { "bluetooth group" };
+        assert!(e.contains(hint), "{e}");
+
+        let e = bluetooth_error(btleplug::Error::DeviceNotFound);
+        assert_eq!(e.to_string(), "Device not found");
+    }
+
+    #[test]
+    fn names_match_case_insensitively_unless_exact() {
+        let controller = GanRobotController::try_new("GAN-Robot", MOVE_UUID, STATUS_UUID).unwrap();
+        assert!(controller.matches_name("GAN-Robot"));
+        assert!(controller.matches_name("gan-robot"));
+        assert!(controller.matches_name("Gan-ROBOT"));
+        assert!(!controller.matches_name("GAN-Robot 2"));
+
+        let controller = controller.with_exact_name_case(true);
+        assert!(controller.matches_name("GAN-Robot"));
+        assert!(!controller.matches_name("gan-robot"));
+    }
+
+    #[tokio::test]
+    async fn signals_below_the_threshold_are_weak() {
+        let robot = fake_robot();
+        robot.set_rssi(Some(-70));
+        let controller = builder(&RecordingSleeper::new())
+            .with_low_rssi_threshold(-80)
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+        assert_eq!(controller.device_info().rssi, Some(-70));
+        assert_eq!(controller.rssi().await.unwrap(), -70);
+
+        assert!(!controller.is_weak_signal(-70));
+        assert!(!controller.is_weak_signal(-80));
+        assert!(controller.is_weak_signal(-81));
+    }
+
+    #[tokio::test]
+    async fn on_move_sent_fires_once_per_move_as_sent() {
+        let robot = fake_robot();
+        let sent = Arc::new(std::sync::Mutex::new(vec![]));
+        let controller = builder(&RecordingSleeper::new())
+            .with_orientation(Orientation::FrontR)
+            .with_max_moves_per_write(2)
+            .unwrap()
+            .with_on_move_sent({
+                let sent = sent.clone();
+                move |m| sent.lock().unwrap().push(m)
+            })
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+
+        controller.do_moves(&moves!("R F2' L'")).await.unwrap();
+        // Remapped for the orientation and without the prime of the double turn.
+        assert_eq!(*sent.lock().unwrap(), moves!("B R2 F'"));
+        assert_eq!(written_moves(&robot), [vec![12, 1], vec![5]]);
+    }
+
+    #[tokio::test]
+    async fn set_speed_fails_without_writing() {
+        let robot = fake_robot();
+        let controller = builder(&RecordingSleeper::new())
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+
+        for level in [0, 1, 255] {
+            let e = controller.set_speed(level).await.unwrap_err();
+            assert_eq!(
+                e.to_string(),
+                format!("Setting the speed to {level} is not supported by the GAN robot firmware")
+            );
+        }
+        assert!(robot.writes(robot.move_characteristic()).is_empty());
+        assert!(robot.writes(robot.status_characteristic()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn estimates_use_the_timing_of_the_controller() {
+        let timing = TimingConfig {
+            quarter_turn: Duration::from_millis(200),
+            ..Default::default()
+        };
+        let controller = builder(&RecordingSleeper::new())
+            .with_timing(timing)
+            .from_peripheral(fake_robot())
+            .await
+            .unwrap();
+
+        let moves = moves!("R F2");
+        assert_eq!(controller.estimated_duration(&moves), Duration::from_millis(450));
+        assert_eq!(estimated_duration(&moves), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn wander_runs_without_a_tokio_runtime() {
+        futures::executor::block_on(async {
+            let robot = fake_robot();
+            let controller = builder(&RecordingSleeper::new())
+                .from_peripheral(robot.clone())
+                .await
+                .unwrap();
+
+            // The cancel completes while sleeping after the first move.
+            controller
+                .wander(Duration::from_millis(500), futures::future::ready(()))
+                .await
+                .unwrap();
+            assert_eq!(written_moves(&robot).len(), 1);
+        });
     }
 }
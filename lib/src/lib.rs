@@ -1,7 +1,54 @@
+mod controller_guard;
+mod csv_log;
+mod cube_state;
+mod device_info;
+mod error;
 mod face_rotation;
+mod fault_flags;
 mod gan_robot_controller;
+mod logging;
+mod move_source;
+mod orientation;
+mod pack;
+mod patterns;
+mod pause_handle;
+mod scramble_source;
+mod sleeper;
+mod subscription;
+#[cfg(any(test, feature = "test-util"))]
+mod test_support;
+mod tnoodle;
+mod watchdog;
 
-pub use face_rotation::{FaceRotation, FaceRotationMap};
-pub use gan_robot_controller::GanRobotController;
+pub use controller_guard::ControllerGuard;
+pub use csv_log::CsvMoveLog;
+pub use cube_state::{is_identity, net_effect, order, CubeState};
+pub use device_info::DeviceInfo;
+pub use error::ControllerError;
+pub use face_rotation::{
+    assert_valid_moves, byte_for, cancel_moves, cancel_notation, face_turn_from_byte,
+    format_grouped, group_sequence, invert_bytes, invert_sequence, normalize_notation,
+    parse_moves_translating_u, scramble_quality, to_written, Face, FaceRotation, FaceRotationMap,
+    Turn, WrittenRotation, U_D_SWAP,
+};
+pub use fault_flags::FaultFlags;
+pub use gan_robot_controller::{
+    broadcast_moves, estimated_duration, write_count, BatchBy, CharacteristicInfo,
+    CompletionPredicate, Connected, DroppedMoveCheck, ErrorPolicy, GanRobotController, OnChunk,
+    OnMoveSent, RobotStatus, ServiceInfo, TimingConfig, Uninitialized, GAN_ROBOT_SERVICE,
+};
+pub use move_source::{run_source, MoveSource, StdinMoveSource};
+pub use orientation::{fold_rotations, Orientation};
+pub use pack::{decode_frame, encode_frames, pack, unpack};
+pub use patterns::{pattern, pattern_state, PATTERNS};
+pub use pause_handle::PauseHandle;
+pub use scramble_source::ScrambleSource;
+pub use sleeper::{Sleeper, TokioSleeper};
+pub use subscription::Subscription;
+#[cfg(any(test, feature = "test-util"))]
+pub use test_support::{FakePeripheral, RecordingSleeper};
+pub use tnoodle::import_tnoodle;
+
+pub use btleplug::api::{CharPropFlags, WriteType};
 
 pub const MAX_MOVES_PER_WRITE: usize = 36;
@@ -0,0 +1,35 @@
+//! Forwards to the `log` crate when the `log` feature is enabled. Without it the macros only
+//! check their arguments, so embedders that do not want logging do not pull in `log`.
+
+#[cfg(feature = "log")]
+pub(crate) use log::{info, trace, warn};
+
+#[cfg(not(feature = "log"))]
+macro_rules! info_ {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! trace_ {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! warn_ {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+#[cfg(not(feature = "log"))]
+// Named differently so they do not clash with the built-in `warn` attribute.
+pub(crate) use {info_ as info, trace_ as trace, warn_ as warn};
+
+/// Formats bytes as space separated hex for the protocol trace, e.g. `04 8f ff`.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().join(" ")
+}
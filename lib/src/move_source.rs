@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+
+use crate::logging::{info, warn};
+use async_trait::async_trait;
+use btleplug::api;
+use tokio::io::{stdin, AsyncBufReadExt, BufReader, Lines, Stdin};
+
+use crate::{Connected, FaceRotation, GanRobotController};
+
+/// A source of moves arriving one by one, e.g. from MIDI or keyboard events. See [`run_source`].
+#[async_trait]
+pub trait MoveSource: Send {
+    /// Returns the next move, waiting until there is one, or `None` once the source is done.
+    async fn next(&mut self) -> Option<FaceRotation>;
+}
+
+/// Does the moves from `source` as they arrive until the source is done.
+pub async fn run_source<P: api::Peripheral + 'static>(
+    controller: &GanRobotController<Connected<P>>,
+    source: &mut impl MoveSource,
+) -> anyhow::Result<()> {
+    info!("Running moves from source");
+    while let Some(m) = source.next().await {
+        controller.do_moves(&[m]).await?;
+    }
+    info!("Move source is done");
+    Ok(())
+}
+
+/// A [`MoveSource`] reading whitespace separated moves from stdin until EOF. Invalid moves are
+/// skipped with a warning.
+pub struct StdinMoveSource {
+    lines: Lines<BufReader<Stdin>>,
+    pending: VecDeque<FaceRotation>,
+}
+
+impl Default for StdinMoveSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StdinMoveSource {
+    pub fn new() -> Self {
+        Self {
+            lines: BufReader::new(stdin()).lines(),
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl MoveSource for StdinMoveSource {
+    async fn next(&mut self) -> Option<FaceRotation> {
+        while self.pending.is_empty() {
+            let line = match self.lines.next_line().await {
+                Ok(Some(line)) => line,
+                Ok(None) => return None,
+                Err(e) => {
+                    warn!("Failed to read from stdin: {e}");
+                    return None;
+                }
+            };
+            for token in line.split_whitespace() {
+                match FaceRotation::from(token) {
+                    FaceRotation::Invalid => warn!("Skipping invalid move: {token}"),
+                    m => self.pending.push_back(m),
+                }
+            }
+        }
+        self.pending.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode_frame, DroppedMoveCheck, FakePeripheral, RecordingSleeper};
+
+    const MOVE_UUID: &str = "0000fff3-0000-1000-8000-00805f9b34fb";
+    const STATUS_UUID: &str = "0000fff2-0000-1000-8000-00805f9b34fb";
+
+    struct ScriptedSource(VecDeque<FaceRotation>);
+
+    #[async_trait]
+    impl MoveSource for ScriptedSource {
+        async fn next(&mut self) -> Option<FaceRotation> {
+            self.0.pop_front()
+        }
+    }
+
+    #[tokio::test]
+    async fn scripted_moves_reach_the_robot_one_write_each() {
+        let robot = FakePeripheral::new(MOVE_UUID, STATUS_UUID).unwrap();
+        robot.push_read(robot.status_characteristic(), vec![0]);
+        let controller = GanRobotController::try_new("GAN", MOVE_UUID, STATUS_UUID)
+            .unwrap()
+            .with_dropped_move_check(DroppedMoveCheck::Off)
+            .with_sleeper(RecordingSleeper::new())
+            .from_peripheral(robot.clone())
+            .await
+            .unwrap();
+
+        let mut source = ScriptedSource(crate::moves!("R F' L2").into());
+        run_source(&controller, &mut source).await.unwrap();
+        let writes = robot.writes(robot.move_characteristic());
+        let written = writes
+            .iter()
+            .map(|frame| decode_frame(frame).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(written, [[0], [5], [10]]);
+    }
+}
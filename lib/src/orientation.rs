@@ -0,0 +1,128 @@
+use std::str::FromStr;
+
+use crate::{face_rotation::parse_moves_translating_u, normalize_notation, Face, FaceRotation};
+
+/// How the robot is placed relative to the user, named by the robot face the user sees as the
+/// front. Moves are remapped so that they turn the faces as the user sees them. D stays D, as the
+/// robot cannot be turned over.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Orientation {
+    #[default]
+    FrontF,
+    FrontR,
+    FrontB,
+    FrontL,
+}
+
+impl Orientation {
+    /// Returns the robot face that turns for the given face as the user sees it.
+    pub fn robot_face(self, face: Face) -> Face {
+        use Face::*;
+        const AROUND: [Face; 4] = [F, R, B, L];
+        let Some(i) = AROUND.iter().position(|&f| f == face) else {
+            return face;
+        };
+        let offset = match self {
+            Self::FrontF => 0,
+            Self::FrontR => 1,
+            Self::FrontB => 2,
+            Self::FrontL => 3,
+        };
+        AROUND[(i + offset) % 4]
+    }
+
+    /// Returns the move the robot has to do for the given move as the user sees it.
+    ///
+    /// ```
+    /// use lib::{FaceRotation, Orientation};
+    ///
+    /// assert_eq!(Orientation::FrontR.remap(FaceRotation::RPrime), FaceRotation::BPrime);
+    /// assert_eq!(Orientation::FrontB.remap(FaceRotation::F2), FaceRotation::B2);
+    /// assert_eq!(Orientation::FrontL.remap(FaceRotation::D), FaceRotation::D);
+    /// ```
+    pub fn remap(self, m: FaceRotation) -> FaceRotation {
+        match m.face() {
+            Some(face) => m.with_face(self.robot_face(face)),
+            None => m,
+        }
+    }
+}
+
+impl FromStr for Orientation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "f" => Ok(Self::FrontF),
+            "r" => Ok(Self::FrontR),
+            "b" => Ok(Self::FrontB),
+            "l" => Ok(Self::FrontL),
+            _ => anyhow::bail!("Invalid orientation: {s}. Must be F, R, B or L"),
+        }
+    }
+}
+
+/// The faces in the order used by [`fold_rotations`].
+const FACES: [char; 6] = ['U', 'R', 'F', 'D', 'L', 'B'];
+
+/// Converts a sequence in standard notation that may contain cube rotations (`x`, `y`, `z`), wide
+/// moves (`Rw` etc.) and slice moves (`M`, `E`, `S`) into face moves the robot can do, with the
+/// same effect on the cube apart from its orientation. Rotations are folded into the following
+/// moves by tracking which physical face each face currently is, and U moves are translated as
+/// in [`parse_moves_translating_u`].
+pub fn fold_rotations(s: &str) -> anyhow::Result<Vec<FaceRotation>> {
+    // The physical face each face in `FACES` is after the rotations so far.
+    let mut frame = FACES;
+    let mut notation = vec![];
+    for token in normalize_notation(s)?.split_whitespace() {
+        if !token.is_ascii() {
+            anyhow::bail!("Invalid move: {token}");
+        }
+        let base_len = if token[1..].starts_with('w') { 2 } else { 1 };
+        let (base, suffix) = token.split_at(base_len.min(token.len()));
+        let turns = match suffix {
+            "" => 1,
+            "2" | "2'" => 2,
+            "'" => 3,
+            _ => anyhow::bail!("Invalid move: {token}"),
+        };
+        // Wide and slice moves are face moves combined with a rotation.
+        let (faces, rotation) = match base {
+            "x" | "y" | "z" => (vec![], Some((base.chars().next().unwrap(), turns))),
+            "Rw" => (vec![('L', turns)], Some(('x', turns))),
+            "Lw" => (vec![('R', turns)], Some(('x', 4 - turns))),
+            "Uw" => (vec![('D', turns)], Some(('y', turns))),
+            "Dw" => (vec![('U', turns)], Some(('y', 4 - turns))),
+            "Fw" => (vec![('B', turns)], Some(('z', turns))),
+            "Bw" => (vec![('F', turns)], Some(('z', 4 - turns))),
+            "M" => (vec![('R', turns), ('L', 4 - turns)], Some(('x', 4 - turns))),
+            "E" => (vec![('U', turns), ('D', 4 - turns)], Some(('y', 4 - turns))),
+            "S" => (vec![('F', 4 - turns), ('B', turns)], Some(('z', turns))),
+            _ => match FACES.iter().find(|f| base == f.to_string()) {
+                Some(&face) => (vec![(face, turns)], None),
+                None => anyhow::bail!("Invalid move: {token}"),
+            },
+        };
+        for (face, turns) in faces {
+            let physical = frame[FACES.iter().position(|&f| f == face).unwrap()];
+            notation.push(format!("{physical}{}", ["", "2", "'"][turns - 1]));
+        }
+        if let Some((axis, turns)) = rotation {
+            (0..turns).for_each(|_| rotate(&mut frame, axis));
+        }
+    }
+    Ok(parse_moves_translating_u(&notation.join(" ")))
+}
+
+/// Applies a clockwise quarter rotation of the whole cube around `axis` to `frame`.
+fn rotate(frame: &mut [char; 6], axis: char) {
+    let [u, r, f, d, l, b] = *frame;
+    *frame = match axis {
+        // Like R: the front is now what was the bottom.
+        'x' => [f, r, d, b, l, u],
+        // Like U: the front is now what was the right.
+        'y' => [u, b, r, d, f, l],
+        // Like F: the top is now what was the left.
+        _ => [l, u, f, r, d, b],
+    };
+}
@@ -0,0 +1,171 @@
+use crate::{FaceRotation, Turn, WrittenRotation, MAX_MOVES_PER_WRITE};
+
+/// The size of a write to the move characteristic in bytes.
+pub(crate) const FRAME_LEN: usize = 18;
+
+/// Packs moves into a compact binary form for storage. The moves come first, two per byte as in
+/// the robot protocol and terminated by a `0xf` nibble, padded to a full byte with another one.
+/// To keep how double turns were written, a bit per double turn follows, set for the primed ones
+/// like `R2'`, least significant bit first. Invalid moves are skipped.
+///
+/// ```
+/// use lib::{moves, pack, unpack, WrittenRotation};
+///
+/// let moves = ["R", "F2'", "D'"].map(WrittenRotation::from);
+/// assert_eq!(pack(&moves), [0x04, 0x8f, 0x01]);
+/// assert_eq!(unpack(&pack(&moves)), moves);
+/// assert_eq!(unpack(&pack(&moves!("R2 B")))[0].to_string(), "R2");
+/// ```
+pub fn pack<M: Copy + Into<WrittenRotation>>(moves: &[M]) -> Vec<u8> {
+    let moves = moves
+        .iter()
+        .map(|&m| m.into())
+        .filter(|m| m.rotation != FaceRotation::Invalid)
+        .collect::<Vec<_>>();
+    let mut bytes = pack_nibbles(&moves.iter().map(|m| u8::from(m.rotation)).collect::<Vec<_>>());
+
+    let primes = moves
+        .iter()
+        .filter(|m| m.rotation.turn() == Some(Turn::Double))
+        .map(|m| m.primed)
+        .collect::<Vec<_>>();
+    bytes.extend(primes.chunks(8).map(|bits| {
+        bits.iter()
+            .enumerate()
+            .fold(0, |byte, (i, &prime)| byte | (prime as u8) << i)
+    }));
+    bytes
+}
+
+/// Unpacks moves packed with [`pack`]. Nibbles that are not moves come out as
+/// [`FaceRotation::Invalid`], and missing prime bits as unprimed double turns.
+pub fn unpack(bytes: &[u8]) -> Vec<WrittenRotation> {
+    let nibbles = bytes.iter().flat_map(|b| [b >> 4, b & 0x0f]);
+    let mut moves = nibbles
+        .take_while(|&n| n != 0x0f)
+        .map(|n| WrittenRotation::from(FaceRotation::from(n)))
+        .collect::<Vec<_>>();
+
+    // The moves end with the byte holding the terminator.
+    let flags = bytes.get(moves.len() / 2 + 1..).unwrap_or_default();
+    let mut primes = flags.iter().flat_map(|b| (0..8).map(move |i| b >> i & 1 == 1));
+    for m in moves.iter_mut().filter(|m| m.rotation.turn() == Some(Turn::Double)) {
+        m.primed = primes.next().unwrap_or(false);
+    }
+    moves
+}
+
+/// Packs protocol values two per byte, high nibble first, followed by a `0xf` terminator and
+/// another `0xf` if needed to fill the last byte.
+pub(crate) fn pack_nibbles(moves: &[u8]) -> Vec<u8> {
+    let mut nibbles = moves.to_vec();
+    nibbles.push(0x0f);
+    if nibbles.len() % 2 == 1 {
+        nibbles.push(0x0f);
+    }
+    nibbles.chunks(2).map(|n| n[0] << 4 | n[1]).collect()
+}
+
+/// Encodes moves into the frames written to the move characteristic, at most
+/// [`MAX_MOVES_PER_WRITE`] per frame and without padding. Invalid moves are skipped. The frames
+/// decode back with [`decode_frame`].
+///
+/// ```
+/// use lib::{decode_frame, encode_frames, moves, FaceRotation};
+///
+/// let roundtrip = |moves: &[FaceRotation]| {
+///     let frames = encode_frames(moves);
+///     let decoded = frames.iter().flat_map(|frame| decode_frame(frame).unwrap());
+///     (frames.len(), decoded.map(FaceRotation::from).collect::<Vec<_>>())
+/// };
+/// assert_eq!(encode_frames(&moves!("R F' L2")), [vec![0x05, 0xaf]]);
+/// assert_eq!(roundtrip(&moves!("R F' L2")), (1, moves!("R F' L2")));
+///
+/// // A full frame has no room for the terminator.
+/// assert_eq!(encode_frames(&[FaceRotation::RPrime; 36]), [vec![0x22; 18]]);
+/// assert_eq!(roundtrip(&[FaceRotation::R; 36]), (1, vec![FaceRotation::R; 36]));
+/// assert_eq!(roundtrip(&[FaceRotation::R; 37]), (2, vec![FaceRotation::R; 37]));
+/// ```
+pub fn encode_frames(moves: &[FaceRotation]) -> Vec<Vec<u8>> {
+    let moves = moves
+        .iter()
+        .filter(|&&m| m != FaceRotation::Invalid)
+        .map(|&m| u8::from(m))
+        .collect::<Vec<_>>();
+    moves.chunks(MAX_MOVES_PER_WRITE).map(pack_frame).collect()
+}
+
+/// Packs at most [`MAX_MOVES_PER_WRITE`] protocol values into a frame as written to the robot,
+/// which is [`pack_nibbles`] without the terminator if the moves fill all [`FRAME_LEN`] bytes.
+pub(crate) fn pack_frame(moves: &[u8]) -> Vec<u8> {
+    let mut frame = pack_nibbles(moves);
+    frame.truncate(FRAME_LEN);
+    frame
+}
+
+/// Decodes a frame as written to the move characteristic, at most 18 bytes with the moves packed
+/// two per byte, high nibble first, and terminated by a `0xf` nibble unless all 36 are moves.
+/// Returns the protocol values of the moves. Every nibble but `0xf` is a move, so this fails on
+/// a missing terminator, or anything but `0xf` after it, so that a malformed frame is caught
+/// before it reaches the robot.
+///
+/// ```
+/// use lib::decode_frame;
+///
+/// assert_eq!(decode_frame(&[0x04, 0x8f]).unwrap(), [0, 4, 8]);
+/// assert_eq!(decode_frame(&[0x04, 0x8f, 0xff]).unwrap(), [0, 4, 8]);
+/// assert!(decode_frame(&[0x0f, 0x4f]).is_err());
+/// assert!(decode_frame(&[0x04, 0x8f, 0x12]).is_err());
+/// assert!(decode_frame(&[0xff; 19]).is_err());
+/// ```
+pub fn decode_frame(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if bytes.len() > FRAME_LEN {
+        anyhow::bail!("Invalid frame: {} bytes. Must be at most {FRAME_LEN}", bytes.len());
+    }
+    let nibbles = bytes.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect::<Vec<_>>();
+    let end = nibbles.iter().position(|&n| n == 0x0f).unwrap_or(nibbles.len());
+    if end < nibbles.len() && nibbles[end..].iter().any(|&n| n != 0x0f) {
+        anyhow::bail!("Invalid frame: moves after the 0xf terminator at nibble {end}");
+    }
+    if end == nibbles.len() && end < FRAME_LEN * 2 {
+        anyhow::bail!("Invalid frame: missing the 0xf terminator");
+    }
+    Ok(nibbles[..end].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_adjacent_pair_packs_into_its_own_byte() {
+        for first in 0..15 {
+            for second in 0..15 {
+                let packed = pack_nibbles(&[first, second]);
+                assert_eq!(packed, [first << 4 | second, 0xff], "{first} {second}");
+                assert_eq!(packed[0] >> 4, first);
+                assert_eq!(packed[0] & 0x0f, second);
+                assert_eq!(decode_frame(&packed).unwrap(), [first, second]);
+            }
+        }
+    }
+
+    #[test]
+    fn odd_lengths_end_with_the_terminator_in_the_low_nibble() {
+        assert_eq!(pack_nibbles(&[]), [0xff]);
+        for m in 0..15 {
+            assert_eq!(pack_nibbles(&[m]), [m << 4 | 0x0f]);
+            assert_eq!(pack_nibbles(&[m, 14, m]), [m << 4 | 14, m << 4 | 0x0f]);
+        }
+    }
+
+    #[test]
+    fn every_length_roundtrips() {
+        let moves = (0..MAX_MOVES_PER_WRITE as u8).map(|i| i * 7 % 15).collect::<Vec<_>>();
+        for len in 0..=MAX_MOVES_PER_WRITE {
+            let frame = pack_frame(&moves[..len]);
+            assert_eq!(frame.len(), (len / 2 + 1).min(FRAME_LEN), "{len}");
+            assert_eq!(decode_frame(&frame).unwrap(), moves[..len], "{len}");
+        }
+    }
+}
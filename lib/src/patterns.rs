@@ -0,0 +1,48 @@
+use crate::{face_rotation::parse_moves_translating_u, CubeState, FaceRotation};
+
+/// Classic patterns from a solved cube, by name. U moves are translated when parsed, see
+/// [`parse_moves_translating_u`].
+///
+/// ```
+/// use lib::{net_effect, pattern, pattern_state, FaceRotation, PATTERNS};
+///
+/// for (name, _) in PATTERNS {
+///     let moves = pattern(name).unwrap();
+///     assert!(!moves.is_empty());
+///     assert!(!moves.contains(&FaceRotation::Invalid), "{name}");
+/// }
+/// assert_eq!(pattern("unknown"), None);
+///
+/// // Every pattern leads to its state.
+/// for (name, _) in PATTERNS {
+///     let state = net_effect(&pattern(name).unwrap());
+///     assert_eq!(Some(state), pattern_state(name), "{name}");
+/// }
+/// ```
+pub const PATTERNS: [(&str, &str); 4] = [
+    ("superflip", "U R2 F B R B2 R U2 L B2 R U' D' R2 F R' L B2 U2 F2"),
+    ("checkerboard", "R2 L2 U2 D2 F2 B2"),
+    ("cube-in-cube", "F L F U' R U F2 L2 U' L' B D' B' L2 U"),
+    ("six-spots", "U D' R L' F B' U D'"),
+];
+
+/// Returns the moves of the pattern with the given name, or `None` if there is no such pattern.
+pub fn pattern(name: &str) -> Option<Vec<FaceRotation>> {
+    PATTERNS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, moves)| parse_moves_translating_u(moves))
+}
+
+/// Returns the state the pattern with the given name leads to from a solved cube. The states are
+/// built from the stickers rather than from the moves in [`PATTERNS`], so that the moves can be
+/// checked against them.
+pub fn pattern_state(name: &str) -> Option<CubeState> {
+    match name {
+        "superflip" => Some(CubeState::superflip()),
+        "checkerboard" => Some(CubeState::checkerboard()),
+        "cube-in-cube" => Some(CubeState::cube_in_cube()),
+        "six-spots" => Some(CubeState::six_spots()),
+        _ => None,
+    }
+}
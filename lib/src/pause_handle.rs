@@ -0,0 +1,58 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use tokio::sync::Notify;
+
+use crate::logging::info;
+
+/// Pauses and resumes the sequences of a controller between chunks, from any task or hook. Get
+/// one with [`crate::GanRobotController::pause_handle`], or share one with
+/// [`crate::GanRobotController::with_pause_handle`]. Clones control the same controller.
+///
+/// Only the start of the next chunk waits. The moves already written are buffered by the robot
+/// and cannot be paused, so it finishes them first.
+#[derive(Debug, Default, Clone)]
+pub struct PauseHandle(Arc<State>);
+
+#[derive(Debug, Default)]
+struct State {
+    paused: AtomicBool,
+    resumed: Notify,
+}
+
+impl PauseHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes a running sequence wait before its next chunk until [`Self::resume`].
+    pub fn pause(&self) {
+        info!("Pausing");
+        self.0.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Resumes a sequence paused with [`Self::pause`] from the next chunk.
+    pub fn resume(&self) {
+        info!("Resuming");
+        self.0.paused.store(false, Ordering::SeqCst);
+        self.0.resumed.notify_waiters();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.0.paused.load(Ordering::SeqCst)
+    }
+
+    pub(crate) async fn wait_while_paused(&self, next_chunk: usize) {
+        loop {
+            // Register before checking the flag so that a resume in between is not missed.
+            let resumed = self.0.resumed.notified();
+            if !self.is_paused() {
+                return;
+            }
+            info!("Paused before chunk {next_chunk}");
+            resumed.await;
+        }
+    }
+}
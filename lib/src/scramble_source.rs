@@ -0,0 +1,31 @@
+use crate::{FaceRotation, FaceRotationMap};
+
+/// A source of scrambles for [`crate::GanRobotController::scramble_from`], e.g. an external
+/// generator producing WCA-certified scrambles. [`FaceRotationMap`] is the built-in one, picking
+/// random moves.
+///
+/// ```
+/// use lib::{moves, FaceRotation, ScrambleSource};
+///
+/// /// Repeats a fixed sequence, e.g. one read from a file.
+/// struct Repeating(Vec<FaceRotation>);
+///
+/// impl ScrambleSource for Repeating {
+///     fn next_scramble(&self, length: usize) -> Vec<FaceRotation> {
+///         self.0.iter().copied().cycle().take(length).collect()
+///     }
+/// }
+///
+/// let source = Repeating(moves!("R D' F2"));
+/// assert_eq!(source.next_scramble(4), moves!("R D' F2 R"));
+/// ```
+pub trait ScrambleSource: Sync {
+    /// Returns a scramble of `length` moves.
+    fn next_scramble(&self, length: usize) -> Vec<FaceRotation>;
+}
+
+impl ScrambleSource for FaceRotationMap {
+    fn next_scramble(&self, length: usize) -> Vec<FaceRotation> {
+        self.get_random_moves(length)
+    }
+}
@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+/// Waits for a given time. The controller sleeps only through this trait, so that it can run on
+/// executors other than Tokio, see [`crate::GanRobotController::with_sleeper`]. Its locks and
+/// channels are from `tokio::sync`, which work on any executor.
+///
+/// A few features need a Tokio runtime, as they spawn background tasks: the inactivity timeout,
+/// the unsubscribe when a [`crate::Subscription`] is dropped and the disconnect when a
+/// [`crate::ControllerGuard`] is dropped. Without a runtime, they warn and do without the task.
+/// [`crate::StdinMoveSource`] reads through Tokio and needs one as well.
+#[async_trait]
+pub trait Sleeper: Send + Sync {
+    async fn sleep(&self, duration: Duration);
+}
+
+/// The default [`Sleeper`], backed by [`tokio::time::sleep`].
+pub struct TokioSleeper;
+
+#[async_trait]
+impl Sleeper for TokioSleeper {
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await;
+    }
+}
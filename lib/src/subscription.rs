@@ -0,0 +1,88 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::logging::{hex, trace, warn};
+use btleplug::{
+    api::{self, Characteristic},
+    platform::Peripheral,
+};
+use futures::{Stream, StreamExt};
+use tokio::runtime::Handle;
+
+/// The notifications of a characteristic, as returned by
+/// [`crate::GanRobotController::subscribe`].
+///
+/// Dropping it unsubscribes in a detached task on the current Tokio runtime, on a best-effort
+/// basis like [`crate::ControllerGuard`]. Call [`Self::unsubscribe`] to wait for it instead.
+pub struct Subscription<P: api::Peripheral + 'static = Peripheral> {
+    peripheral: P,
+    characteristic: Option<Characteristic>,
+    notifications: Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>,
+}
+
+impl<P: api::Peripheral + 'static> Subscription<P> {
+    pub(crate) async fn new(
+        peripheral: &P,
+        characteristic: Characteristic,
+    ) -> anyhow::Result<Self> {
+        // Get the stream first so that no notification is missed.
+        let uuid = characteristic.uuid;
+        let notifications = peripheral
+            .notifications()
+            .await?
+            .filter(move |n| futures::future::ready(n.uuid == uuid))
+            .map(move |n| {
+                trace!("Notification {uuid}: {}", hex(&n.value));
+                n.value
+            });
+        trace!("Subscribing to {uuid}");
+        peripheral.subscribe(&characteristic).await?;
+        Ok(Self {
+            peripheral: peripheral.clone(),
+            characteristic: Some(characteristic),
+            notifications: Box::pin(notifications),
+        })
+    }
+
+    pub async fn unsubscribe(mut self) -> anyhow::Result<()> {
+        match self.characteristic.take() {
+            Some(characteristic) => {
+                trace!("Unsubscribing from {}", characteristic.uuid);
+                Ok(self.peripheral.unsubscribe(&characteristic).await?)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+// Nothing is pinned structurally, the stream is boxed already.
+impl<P: api::Peripheral + 'static> Unpin for Subscription<P> {}
+
+impl<P: api::Peripheral + 'static> Stream for Subscription<P> {
+    type Item = Vec<u8>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.notifications.as_mut().poll_next(cx)
+    }
+}
+
+impl<P: api::Peripheral + 'static> Drop for Subscription<P> {
+    fn drop(&mut self) {
+        let Some(characteristic) = self.characteristic.take() else {
+            return;
+        };
+        let peripheral = self.peripheral.clone();
+        match Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move {
+                    if let Err(e) = peripheral.unsubscribe(&characteristic).await {
+                        warn!("Failed to unsubscribe from {} on drop: {e}", characteristic.uuid);
+                    }
+                });
+            }
+            Err(_) => warn!("No Tokio runtime to unsubscribe from {} on drop", characteristic.uuid),
+        }
+    }
+}
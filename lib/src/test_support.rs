@@ -0,0 +1,364 @@
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use btleplug::{
+    api::{
+        self, BDAddr, CharPropFlags, Characteristic, Descriptor, PeripheralProperties, Service,
+        ValueNotification, WriteType,
+    },
+    platform::PeripheralId,
+};
+use futures::Stream;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::{Sleeper, GAN_ROBOT_SERVICE};
+
+/// An in-memory stand-in for the robot, to test code using the controller without Bluetooth.
+/// Pass it to [`crate::GanRobotController::from_peripheral`]. Clones share their state, so keep
+/// one to script responses and inspect writes while the controller owns another.
+///
+/// The fake does not simulate the robot. Reads are answered from a script instead: each
+/// characteristic has a queue of values filled with [`Self::push_read`], and every read takes
+/// the next one, except that the last value is kept and returned for all following reads. A
+/// read with nothing queued fails. Since the controller reads the remaining move count before
+/// and after each write and then until it is 0, doing 3 moves can be scripted as:
+///
+/// ```
+/// # use lib::FakePeripheral;
+/// # let robot = FakePeripheral::new(
+/// #     "0000fff3-0000-1000-8000-00805f9b34fb",
+/// #     "0000fff2-0000-1000-8000-00805f9b34fb",
+/// # )
+/// # .unwrap();
+/// # let status = robot.status_characteristic();
+/// for remaining in [0, 3, 2, 1, 0] {
+///     robot.push_read(status, vec![remaining]);
+/// }
+/// ```
+///
+/// Writes are recorded and returned by [`Self::writes`], and [`Self::notify`] sends a
+/// notification to all subscribers. Every fake gets an address of its own, with a platform id
+/// made from it.
+#[derive(Clone, Debug)]
+pub struct FakePeripheral {
+    id: PeripheralId,
+    state: Arc<Mutex<FakeState>>,
+    notifications: broadcast::Sender<ValueNotification>,
+    move_characteristic: Uuid,
+    status_characteristic: Uuid,
+}
+
+#[derive(Debug)]
+struct FakeState {
+    services: BTreeSet<Service>,
+    properties: PeripheralProperties,
+    connected: bool,
+    reads: HashMap<Uuid, VecDeque<Vec<u8>>>,
+    writes: Vec<(Uuid, Vec<u8>, WriteType)>,
+    subscribed: BTreeSet<Uuid>,
+}
+
+impl FakePeripheral {
+    /// Creates a disconnected fake with the given move and status characteristics on
+    /// [`GAN_ROBOT_SERVICE`], with the properties the controller requires.
+    pub fn new(move_characteristic: &str, status_characteristic: &str) -> anyhow::Result<Self> {
+        let service_uuid = Uuid::parse_str(GAN_ROBOT_SERVICE)?;
+        let move_characteristic = Uuid::parse_str(move_characteristic)?;
+        let status_characteristic = Uuid::parse_str(status_characteristic)?;
+        let characteristic = |uuid, properties| Characteristic {
+            uuid,
+            service_uuid,
+            properties,
+            descriptors: BTreeSet::new(),
+        };
+        let characteristics = BTreeSet::from([
+            characteristic(move_characteristic, CharPropFlags::WRITE_WITHOUT_RESPONSE),
+            characteristic(status_characteristic, CharPropFlags::READ | CharPropFlags::NOTIFY),
+        ]);
+        let services =
+            BTreeSet::from([Service { uuid: service_uuid, primary: true, characteristics }]);
+        let (notifications, _) = broadcast::channel(64);
+        // Distinct addresses keep fakes apart in code that tells peripherals apart by id.
+        static NEXT_ADDRESS: AtomicU64 = AtomicU64::new(1);
+        let address = BDAddr::try_from(NEXT_ADDRESS.fetch_add(1, Ordering::Relaxed))?;
+        Ok(Self {
+            id: peripheral_id(address)?,
+            state: Arc::new(Mutex::new(FakeState {
+                services,
+                properties: PeripheralProperties { address, ..Default::default() },
+                connected: false,
+                reads: HashMap::new(),
+                writes: vec![],
+                subscribed: BTreeSet::new(),
+            })),
+            notifications,
+            move_characteristic,
+            status_characteristic,
+        })
+    }
+
+    /// Adds another characteristic on [`GAN_ROBOT_SERVICE`], e.g. one set with
+    /// [`crate::GanRobotController::with_capacity_characteristic`].
+    pub fn add_characteristic(&self, uuid: &str, properties: CharPropFlags) -> anyhow::Result<()> {
+        let uuid = Uuid::parse_str(uuid)?;
+        let mut state = self.state.lock().unwrap();
+        let mut service = state.services.pop_first().unwrap();
+        service.characteristics.insert(Characteristic {
+            uuid,
+            service_uuid: service.uuid,
+            properties,
+            descriptors: BTreeSet::new(),
+        });
+        state.services.insert(service);
+        Ok(())
+    }
+
+    /// Returns the UUID of the status characteristic given to [`Self::new`].
+    pub fn status_characteristic(&self) -> Uuid {
+        self.status_characteristic
+    }
+
+    /// Returns the UUID of the move characteristic given to [`Self::new`].
+    pub fn move_characteristic(&self) -> Uuid {
+        self.move_characteristic
+    }
+
+    /// Queues `value` as the answer to a read of `characteristic`, see [`FakePeripheral`].
+    pub fn push_read(&self, characteristic: Uuid, value: Vec<u8>) {
+        let mut state = self.state.lock().unwrap();
+        state.reads.entry(characteristic).or_default().push_back(value);
+    }
+
+    /// Sends `value` as a notification of `characteristic`, if it is subscribed to.
+    pub fn notify(&self, characteristic: Uuid, value: Vec<u8>) {
+        if self.state.lock().unwrap().subscribed.contains(&characteristic) {
+            // Sending only fails without receivers, in which case nobody is listening anyway.
+            let _ = self
+                .notifications
+                .send(ValueNotification { uuid: characteristic, value });
+        }
+    }
+
+    /// Returns everything written to `characteristic` so far, in order.
+    pub fn writes(&self, characteristic: Uuid) -> Vec<Vec<u8>> {
+        let state = self.state.lock().unwrap();
+        let writes = state.writes.iter().filter(|(uuid, ..)| *uuid == characteristic);
+        writes.map(|(_, value, _)| value.clone()).collect()
+    }
+
+    /// Returns the write type of everything written to `characteristic` so far, in order.
+    pub fn write_types(&self, characteristic: Uuid) -> Vec<WriteType> {
+        let state = self.state.lock().unwrap();
+        let writes = state.writes.iter().filter(|(uuid, ..)| *uuid == characteristic);
+        writes.map(|&(.., write_type)| write_type).collect()
+    }
+
+    /// Sets whether the fake is connected, e.g. to simulate a connection drop.
+    pub fn set_connected(&self, connected: bool) {
+        self.state.lock().unwrap().connected = connected;
+    }
+
+    /// Sets the signal strength reported in the properties.
+    pub fn set_rssi(&self, rssi: Option<i16>) {
+        self.state.lock().unwrap().properties.rssi = rssi;
+    }
+
+    fn check_connected(&self) -> btleplug::Result<()> {
+        if self.state.lock().unwrap().connected {
+            Ok(())
+        } else {
+            Err(btleplug::Error::NotConnected)
+        }
+    }
+}
+
+/// Makes a platform id for a fake with `address`, in the form the platform uses.
+fn peripheral_id(address: BDAddr) -> anyhow::Result<PeripheralId> {
+    #[cfg(target_os = "linux")]
+    {
+        // BlueZ device ids can only be made by bluez-async, but they deserialize from the D-Bus
+        // object path.
+        let path = format!("/org/bluez/hci0/dev_{}", address.to_string().replace(':', "_"));
+        let device_id: bluez_async::DeviceId =
+            serde_json::from_value(serde_json::json!({ "object_path": path }))?;
+        Ok(PeripheralId::from(device_id))
+    }
+    #[cfg(target_vendor = "apple")]
+    {
+        Ok(PeripheralId::from(Uuid::from_u64_pair(0, u64::from(address))))
+    }
+    #[cfg(target_os = "windows")]
+    {
+        Ok(PeripheralId::from(address))
+    }
+    #[cfg(not(any(target_os = "linux", target_vendor = "apple", target_os = "windows")))]
+    {
+        anyhow::bail!("FakePeripheral is not supported on this platform: no id for {address}")
+    }
+}
+
+#[async_trait]
+impl api::Peripheral for FakePeripheral {
+    fn id(&self) -> PeripheralId {
+        self.id.clone()
+    }
+
+    fn address(&self) -> BDAddr {
+        self.state.lock().unwrap().properties.address
+    }
+
+    async fn properties(&self) -> btleplug::Result<Option<PeripheralProperties>> {
+        Ok(Some(self.state.lock().unwrap().properties.clone()))
+    }
+
+    fn services(&self) -> BTreeSet<Service> {
+        self.state.lock().unwrap().services.clone()
+    }
+
+    async fn is_connected(&self) -> btleplug::Result<bool> {
+        Ok(self.state.lock().unwrap().connected)
+    }
+
+    async fn connect(&self) -> btleplug::Result<()> {
+        self.set_connected(true);
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> btleplug::Result<()> {
+        self.set_connected(false);
+        Ok(())
+    }
+
+    async fn discover_services(&self) -> btleplug::Result<()> {
+        self.check_connected()
+    }
+
+    async fn write(
+        &self,
+        characteristic: &Characteristic,
+        data: &[u8],
+        write_type: WriteType,
+    ) -> btleplug::Result<()> {
+        self.check_connected()?;
+        self.state
+            .lock()
+            .unwrap()
+            .writes
+            .push((characteristic.uuid, data.to_vec(), write_type));
+        Ok(())
+    }
+
+    async fn read(&self, characteristic: &Characteristic) -> btleplug::Result<Vec<u8>> {
+        self.check_connected()?;
+        let mut state = self.state.lock().unwrap();
+        let queue = state.reads.entry(characteristic.uuid).or_default();
+        match queue.len() {
+            0 => Err(btleplug::Error::Other(
+                format!("No read scripted for {}", characteristic.uuid).into(),
+            )),
+            1 => Ok(queue[0].clone()),
+            _ => Ok(queue.pop_front().unwrap()),
+        }
+    }
+
+    async fn subscribe(&self, characteristic: &Characteristic) -> btleplug::Result<()> {
+        self.check_connected()?;
+        self.state.lock().unwrap().subscribed.insert(characteristic.uuid);
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, characteristic: &Characteristic) -> btleplug::Result<()> {
+        self.state.lock().unwrap().subscribed.remove(&characteristic.uuid);
+        Ok(())
+    }
+
+    async fn notifications(
+        &self,
+    ) -> btleplug::Result<Pin<Box<dyn Stream<Item = ValueNotification> + Send>>> {
+        let receiver = self.notifications.subscribe();
+        Ok(Box::pin(futures::stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(notification) => return Some((notification, receiver)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })))
+    }
+
+    async fn write_descriptor(
+        &self,
+        _descriptor: &Descriptor,
+        _data: &[u8],
+    ) -> btleplug::Result<()> {
+        self.check_connected()
+    }
+
+    async fn read_descriptor(&self, _descriptor: &Descriptor) -> btleplug::Result<Vec<u8>> {
+        self.check_connected()?;
+        Ok(vec![])
+    }
+}
+
+/// A [`Sleeper`] that returns at once and records how long it was asked to sleep, to test the
+/// waiting of the controller without waiting. Pass it to
+/// [`crate::GanRobotController::with_sleeper`]. Clones share the record.
+///
+/// It still yields to other tasks, so that something running alongside can make progress, but
+/// time does not pass, so a robot whose remaining count never changes is only found stuck
+/// after the real stuck timeout.
+#[derive(Clone, Debug, Default)]
+pub struct RecordingSleeper(Arc<Mutex<Vec<Duration>>>);
+
+impl RecordingSleeper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the durations of all sleeps so far, in order.
+    pub fn durations(&self) -> Vec<Duration> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Sleeper for RecordingSleeper {
+    async fn sleep(&self, duration: Duration) {
+        self.0.lock().unwrap().push(duration);
+        tokio::task::yield_now().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use btleplug::api::Peripheral as _;
+
+    use super::*;
+
+    fn fake() -> FakePeripheral {
+        FakePeripheral::new(
+            "0000fff3-0000-1000-8000-00805f9b34fb",
+            "0000fff2-0000-1000-8000-00805f9b34fb",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn id_is_shared_by_clones_and_unique_per_fake() {
+        let robot = fake();
+        let other = fake();
+        assert_eq!(robot.id(), robot.clone().id());
+        assert_ne!(robot.id(), other.id());
+        assert_ne!(robot.address(), other.address());
+    }
+}
@@ -0,0 +1,72 @@
+use std::{fs, path::Path};
+
+use serde_json::Value;
+
+use crate::{face_rotation::parse_moves_translating_u, normalize_notation, FaceRotation};
+
+/// Imports the 3x3 scrambles from a TNoodle export, either the JSON interchange file or a plain
+/// text file with one scramble per line. U moves are translated as the robot cannot do them, see
+/// [`parse_moves_translating_u`], and turn counts like `R3` are normalized, see
+/// [`normalize_notation`].
+pub fn import_tnoodle(path: impl AsRef<Path>) -> anyhow::Result<Vec<Vec<FaceRotation>>> {
+    let content = fs::read_to_string(path)?;
+    let scrambles = if content.trim_start().starts_with('{') {
+        scrambles_from_json(&serde_json::from_str(&content)?)?
+    } else {
+        scrambles_from_text(&content)
+    };
+    scrambles
+        .iter()
+        .map(|s| Ok(parse_moves_translating_u(&normalize_notation(s)?)))
+        .collect()
+}
+
+fn scrambles_from_json(json: &Value) -> anyhow::Result<Vec<String>> {
+    // Newer TNoodle versions export WCIF, older ones a list of sheets.
+    let sets = if let Some(events) = json.pointer("/wcif/events").and_then(Value::as_array) {
+        events
+            .iter()
+            .filter(|e| is_3x3(&e["id"]))
+            .filter_map(|e| e["rounds"].as_array())
+            .flatten()
+            .filter_map(|r| r["scrambleSets"].as_array())
+            .flatten()
+            .collect::<Vec<_>>()
+    } else if let Some(sheets) = json["sheets"].as_array() {
+        sheets.iter().filter(|s| is_3x3(&s["event"])).collect::<Vec<_>>()
+    } else {
+        anyhow::bail!("Unknown TNoodle JSON format");
+    };
+
+    Ok(sets
+        .iter()
+        .filter_map(|s| s["scrambles"].as_array())
+        .flatten()
+        .filter_map(Value::as_str)
+        // Multi-blind attempts contain several scrambles separated by newlines.
+        .flat_map(str::lines)
+        .map(str::to_string)
+        .collect())
+}
+
+fn scrambles_from_text(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(strip_numbering)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Trims `line` and strips numbering like `1.` or `1)` from it.
+pub(crate) fn strip_numbering(line: &str) -> &str {
+    let line = line.trim();
+    match line.split_once(['.', ')']) {
+        Some((n, rest)) if n.chars().all(|c| c.is_ascii_digit()) => rest.trim(),
+        _ => line,
+    }
+}
+
+fn is_3x3(event: &Value) -> bool {
+    event.as_str().is_some_and(|id| id.starts_with("333"))
+}
@@ -0,0 +1,113 @@
+use std::sync::{Arc, Mutex};
+
+use btleplug::api;
+use tokio::{
+    runtime::Handle,
+    sync::watch,
+    task::AbortHandle,
+    time::{Duration, Instant},
+};
+
+use crate::logging::{info, warn};
+use crate::{ControllerError, Sleeper};
+
+/// Disconnects from the robot once no moves were issued for a while, see
+/// [`crate::GanRobotController::with_inactivity_timeout`]. The timer only runs while no moves are
+/// in progress.
+pub(crate) struct Watchdog {
+    timeout: Duration,
+    state: Arc<Mutex<State>>,
+    disconnected: watch::Receiver<bool>,
+    task: AbortHandle,
+}
+
+struct State {
+    last_activity: Instant,
+    active: usize,
+    expired: bool,
+}
+
+/// Marks moves as in progress until dropped, which restarts the timer.
+pub(crate) struct Activity(Arc<Mutex<State>>);
+
+impl Watchdog {
+    /// Starts the watchdog on the current Tokio runtime, or returns `None` with a warning if
+    /// there is none.
+    pub(crate) fn spawn<P: api::Peripheral + 'static>(
+        peripheral: P,
+        timeout: Duration,
+        sleeper: Arc<dyn Sleeper>,
+    ) -> Option<Self> {
+        let Ok(handle) = Handle::try_current() else {
+            warn!("No Tokio runtime to run the inactivity timeout on, staying connected");
+            return None;
+        };
+        let state = Arc::new(Mutex::new(State {
+            last_activity: Instant::now(),
+            active: 0,
+            expired: false,
+        }));
+        let (tx, disconnected) = watch::channel(false);
+        let task = handle
+            .spawn({
+                let state = state.clone();
+                async move {
+                    loop {
+                        let wait = {
+                            let mut state = state.lock().unwrap();
+                            let idle = state.last_activity.elapsed();
+                            if state.active == 0 && idle >= timeout {
+                                state.expired = true;
+                                break;
+                            }
+                            // Moves in progress restart the timer when they are done.
+                            timeout.saturating_sub(idle).max(Duration::from_millis(1))
+                        };
+                        sleeper.sleep(wait).await;
+                    }
+                    info!("Disconnecting from GAN robot after {timeout:?} without moves");
+                    if let Err(e) = peripheral.disconnect().await {
+                        warn!("Failed to disconnect after the inactivity timeout: {e}");
+                    }
+                    let _ = tx.send(true);
+                }
+            })
+            .abort_handle();
+        Some(Self { timeout, state, disconnected, task })
+    }
+
+    /// Marks moves as in progress, or fails with
+    /// [`ControllerError::DisconnectedForInactivity`] if the watchdog has disconnected.
+    pub(crate) fn start_activity(&self) -> anyhow::Result<Activity> {
+        let mut state = self.state.lock().unwrap();
+        if state.expired {
+            return Err(ControllerError::DisconnectedForInactivity { timeout: self.timeout }.into());
+        }
+        state.active += 1;
+        Ok(Activity(self.state.clone()))
+    }
+
+    /// Returns `true` once the watchdog has decided to disconnect.
+    pub(crate) fn has_expired(&self) -> bool {
+        self.state.lock().unwrap().expired
+    }
+
+    /// Waits until the watchdog has disconnected.
+    pub(crate) async fn disconnected(&self) {
+        let _ = self.disconnected.clone().wait_for(|&disconnected| disconnected).await;
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl Drop for Activity {
+    fn drop(&mut self) {
+        let mut state = self.0.lock().unwrap();
+        state.active -= 1;
+        state.last_activity = Instant::now();
+    }
+}